@@ -5,7 +5,6 @@
 // those terms.
 
 use std::{
-    collections::HashMap,
     fs::File,
     io::Write,
     sync::mpsc::{self, Receiver},
@@ -13,34 +12,14 @@ use std::{
     time::{self, SystemTime},
 };
 
-use marvelmind::{self as mm, DeviceList};
+use marvelmind::{self as mm, Connection, Device};
 
 const LOG_PATH: &str = "E:\\VSRepos\\mm\\log.csv";
 const SAVE_ADDRESS: u8 = 11;
 
-fn save_locations(rx: Receiver<DeviceList>, mut outfile: File) {
-    let mut update_times = HashMap::<u8, SystemTime>::new();
-
-    loop {
-        let Ok(device_list) = rx.recv() else {
-            break;
-        };
-
-        let devices = device_list.devices();
-
+fn save_locations(rx: Receiver<Vec<Device>>, mut outfile: File) {
+    while let Ok(devices) = rx.recv() {
         for device in devices {
-            if !update_times.contains_key(&device.address()) {
-                update_times.insert(device.address(), SystemTime::UNIX_EPOCH);
-            }
-
-            let prev_time = update_times.get(&device.address()).unwrap();
-
-            if prev_time >= &device.update_time() {
-                continue;
-            } else {
-                update_times.insert(device.address(), device.update_time());
-            }
-
             if device.q() > 0 {
                 println!(
                     "address #{:0>3} x {:.3} y {:.3} z {:.3} q {}",
@@ -54,7 +33,7 @@ fn save_locations(rx: Receiver<DeviceList>, mut outfile: File) {
 
             if device.address() == SAVE_ADDRESS {
                 outfile
-                    .write(
+                    .write_all(
                         format!(
                             "{},{},{},{},{},{}\n",
                             device.address(),
@@ -81,22 +60,31 @@ fn main() {
 
     println!("api version: {}", version);
 
-    mm::open_port(30).unwrap();
+    let connection = Connection::open(30).unwrap();
 
     println!("open port successfully");
 
-    let mut devices_list = mm::get_device_list().unwrap();
+    let mut devices_list = connection.get_device_list().unwrap();
 
     let mut outfile = File::create(LOG_PATH).unwrap();
-    outfile.write("address,x,y,z,q,t\n".as_bytes()).unwrap();
+    outfile.write_all("address,x,y,z,q,t\n".as_bytes()).unwrap();
 
     let (tx, rx) = mpsc::channel();
 
     spawn(|| save_locations(rx, outfile));
 
     loop {
-        if devices_list.update_last_locations().unwrap() {
-            tx.send(devices_list.clone()).unwrap();
+        let updated = devices_list.update_last_locations(&connection).unwrap();
+
+        if !updated.is_empty() {
+            let devices = devices_list
+                .devices()
+                .iter()
+                .filter(|device| updated.contains(&device.address()))
+                .cloned()
+                .collect();
+
+            tx.send(devices).unwrap();
         }
 
         sleep(time::Duration::from_millis(1));
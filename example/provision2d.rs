@@ -0,0 +1,47 @@
+// Copyright 2025 wyzdwdz <wyzdwdz@gmail.com>
+//
+// Licensed under the MIT license <LICENSE or https://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to
+// those terms.
+
+use marvelmind::Connection;
+
+const SUBMAP_ID: u8 = 0;
+const HEDGES_HEIGHT_CM: i16 = 120;
+const BEACON_HEIGHTS_MM: [(u8, i32); 4] = [(1, 2800), (2, 2800), (3, 2750), (4, 2750)];
+
+fn main() {
+    let connection = Connection::open(30).unwrap();
+
+    println!("open port successfully");
+
+    let devices_list = connection.get_device_list().unwrap();
+
+    for (address, height_mm) in BEACON_HEIGHTS_MM {
+        if devices_list.get(address).is_none() {
+            println!("beacon #{:0>3} not found, skipped", address);
+            continue;
+        }
+
+        connection.set_beacon_height(address, height_mm).unwrap();
+
+        println!(
+            "beacon #{:0>3} height {} mm",
+            address,
+            connection.get_beacon_height(address).unwrap()
+        );
+    }
+
+    let mut settings = connection.get_submap_settings(SUBMAP_ID).unwrap();
+    settings.is_3d = false;
+    settings.hedges_height_2d_cm = HEDGES_HEIGHT_CM;
+    connection
+        .set_submap_settings(SUBMAP_ID, &settings)
+        .unwrap();
+
+    connection.freeze_submap(SUBMAP_ID).unwrap();
+
+    println!("submap {} configured as 2D and frozen", SUBMAP_ID);
+
+    connection.close().unwrap();
+}
@@ -0,0 +1,87 @@
+// Copyright 2025 wyzdwdz <wyzdwdz@gmail.com>
+//
+// Licensed under the MIT license <LICENSE or https://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to
+// those terms.
+
+use std::collections::HashMap;
+
+use crate::{Connection, Coordinate, DeviceLayer, DeviceList, MMError, PositionFilter};
+
+/// Builder of a devices list, configured before reading it with `get_device_list`
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use marvelmind::{Connection, Coordinate, DeviceListBuilder};
+///
+/// let connection = Connection::open(30).unwrap();
+///
+/// let mut devices_list = DeviceListBuilder::new()
+///     .with_initial_coordinate(10, Coordinate::from_meters(1.0, 2.0, 0.0))
+///     .with_quality_threshold(50)
+///     .build(&connection)
+///     .unwrap();
+///
+/// devices_list.update_last_locations(&connection).unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DeviceListBuilder {
+    initial_coordinates: HashMap<u8, Coordinate>,
+    min_q: u8,
+    history_depth: usize,
+}
+
+impl DeviceListBuilder {
+    /// Create a builder with no configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the coordinate of the devices with `address` until their first location update.
+    ///
+    /// Addresses not in the devices list are ignored.
+    pub fn with_initial_coordinate(mut self, address: u8, coordinate: Coordinate) -> Self {
+        self.initial_coordinates.insert(address, coordinate);
+        self
+    }
+
+    /// Skip the locations with quality below `min_q`, 0...100%, in the location updates of the list.
+    pub fn with_quality_threshold(mut self, min_q: u8) -> Self {
+        self.min_q = min_q;
+        self
+    }
+
+    /// Keep a moving average of the last `depth` locations of each device, see [`DeviceList::history`].
+    ///
+    /// A depth of 0, the default, keeps no history.
+    pub fn with_history_depth(mut self, depth: usize) -> Self {
+        self.history_depth = depth;
+        self
+    }
+
+    /// Read the devices list with [`Connection::get_device_list`] and apply the configuration.
+    pub fn build(self, connection: &Connection) -> Result<DeviceList, MMError> {
+        self.build_with(connection)
+    }
+
+    /// Read the devices list from `layer` and apply the configuration.
+    pub fn build_with(self, layer: &dyn DeviceLayer) -> Result<DeviceList, MMError> {
+        let mut device_list = layer.get_device_list()?;
+
+        for device in &mut device_list.devices {
+            if let Some(coordinate) = self.initial_coordinates.get(&device.address) {
+                device.x = coordinate.x;
+                device.y = coordinate.y;
+                device.z = coordinate.z;
+            }
+        }
+
+        device_list.min_q = self.min_q;
+        if self.history_depth > 0 {
+            device_list.history = Some(PositionFilter::new(self.history_depth));
+        }
+
+        Ok(device_list)
+    }
+}
@@ -0,0 +1,128 @@
+// Copyright 2025 wyzdwdz <wyzdwdz@gmail.com>
+//
+// Licensed under the MIT license <LICENSE or https://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to
+// those terms.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime},
+};
+
+/// Estimator of the offset between the millisecond counter of a Marvelmind<sup>&copy;</sup> device
+/// and the host clock, e.g. a counter sent by the hedgehog in its user payload
+///
+/// The counter is unwrapped across its `u32` wraparound, so the device time keeps increasing.
+/// The offset is the minimum of `host time - device time` over the last samples,
+/// which filters out the varying delay of USB and radio.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use marvelmind::{Connection, DeviceClock};
+/// use std::time::SystemTime;
+///
+/// let connection = Connection::open(30).unwrap();
+///
+/// let mut devices_list = connection.get_device_list().unwrap();
+/// let mut clock = DeviceClock::new(32);
+///
+/// loop {
+///     devices_list.update_last_locations(&connection).unwrap();
+///
+///     // the hedgehog's UART sends its millisecond counter as 4 little-endian bytes
+///     let Some(&[a, b, c, d]) = devices_list.last_payload() else {
+///         continue;
+///     };
+///     let device_ms = u32::from_le_bytes([a, b, c, d]);
+///     clock.ingest(device_ms, SystemTime::now());
+///
+///     println!("{:?}", clock.to_host(device_ms));
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DeviceClock {
+    window: usize,
+    last_raw: Option<u32>,
+    wraps: u64,
+    offsets_ms: VecDeque<i128>,
+}
+
+impl DeviceClock {
+    /// Create an estimator over the last `window` samples, at least 1.
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            last_raw: None,
+            wraps: 0,
+            offsets_ms: VecDeque::new(),
+        }
+    }
+
+    /// Add a sample of the device counter `device_ms` received at `host_time`.
+    ///
+    /// Return the unwrapped device time, ms.
+    pub fn ingest(&mut self, device_ms: u32, host_time: SystemTime) -> u64 {
+        if let Some(last_raw) = self.last_raw {
+            // a jump back of more than half the range is a wraparound, not a late sample
+            if device_ms < last_raw && last_raw - device_ms > u32::MAX / 2 {
+                self.wraps += 1;
+            }
+        }
+        self.last_raw = Some(device_ms);
+
+        let device_time = self.unwrap(device_ms);
+
+        if self.offsets_ms.len() == self.window {
+            self.offsets_ms.pop_front();
+        }
+        self.offsets_ms
+            .push_back(unix_ms(host_time) - device_time as i128);
+
+        device_time
+    }
+
+    /// Get the estimated `host time - device time` offset, ms, `None` before the first sample.
+    pub fn offset_ms(&self) -> Option<i128> {
+        self.offsets_ms.iter().min().copied()
+    }
+
+    /// Convert the device counter `device_ms` to host time, `None` before the first sample.
+    ///
+    /// The counter is unwrapped relative to the last sample.
+    pub fn to_host(&self, device_ms: u32) -> Option<SystemTime> {
+        let host_ms = self.unwrap(device_ms) as i128 + self.offset_ms()?;
+
+        u64::try_from(host_ms)
+            .ok()
+            .map(|host_ms| SystemTime::UNIX_EPOCH + Duration::from_millis(host_ms))
+    }
+
+    /// Convert `host_time` to unwrapped device time, ms, `None` before the first sample.
+    pub fn to_device(&self, host_time: SystemTime) -> Option<u64> {
+        u64::try_from(unix_ms(host_time) - self.offset_ms()?).ok()
+    }
+
+    fn unwrap(&self, device_ms: u32) -> u64 {
+        let mut wraps = self.wraps;
+
+        if let Some(last_raw) = self.last_raw {
+            if device_ms > last_raw && device_ms - last_raw > u32::MAX / 2 {
+                // a late sample from before the last wraparound
+                wraps = wraps.saturating_sub(1);
+            } else if device_ms < last_raw && last_raw - device_ms > u32::MAX / 2 {
+                // a sample after a wraparound not ingested yet
+                wraps += 1;
+            }
+        }
+
+        (wraps << 32) | device_ms as u64
+    }
+}
+
+fn unix_ms(time: SystemTime) -> i128 {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(duration) => duration.as_millis() as i128,
+        Err(err) => -(err.duration().as_millis() as i128),
+    }
+}
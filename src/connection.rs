@@ -0,0 +1,499 @@
+// Copyright 2025 wyzdwdz <wyzdwdz@gmail.com>
+//
+// Licensed under the MIT license <LICENSE or https://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to
+// those terms.
+
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    marker::PhantomData,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use crate::{
+    CancellationToken, Coordinate, Device, DeviceIdentity, DeviceList, Distance, EraseConfirm,
+    LocationFix, MMError, SubmapSettings,
+};
+
+static IS_OPEN: AtomicBool = AtomicBool::new(false);
+
+/// The single open connection to Marvelmind<sup>&copy;</sup> modem
+///
+/// The api keeps global state and is not reentrant, so at most 1 connection can be open at a time,
+/// and a connection can be moved to another thread but not shared between threads.
+/// The port is closed when the connection is dropped.
+///
+/// Every api call of the crate, but [`api_version`](crate::api_version), is made through the connection,
+/// so the api cannot be used before the port is opened or from 2 threads at once.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use marvelmind::Connection;
+///
+/// let connection = Connection::open(30).unwrap();
+/// let mut devices_list = connection.get_device_list().unwrap();
+///
+/// loop {
+///     connection.update_last_locations(&mut devices_list).unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Connection {
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl Connection {
+    /// Opens port where Marvelmind<sup>&copy;</sup> device (modem or beacon) is connected via USB (virtual serial port).
+    /// You don’t need to specify serial port name, because the API searching all serial ports and checks whether it corresponds to Marvelmind device or no.
+    ///
+    /// # Arguments
+    /// * `timeout` - Maximum wait time in seconds before aborting.
+    ///   Note: A value of 0 will attempt exactly one opening attempt.
+    ///
+    /// Return `MMError::AlreadyOpen` if another connection is open.
+    pub fn open(timeout: u64) -> Result<Self, MMError> {
+        Self::open_cancellable(timeout, &CancellationToken::new())
+    }
+
+    /// Opens port like [`open`](Self::open), stopping with `MMError::Cancelled` once `cancel` is cancelled.
+    ///
+    /// Return `MMError::AlreadyOpen` if another connection is open.
+    pub fn open_cancellable(timeout: u64, cancel: &CancellationToken) -> Result<Self, MMError> {
+        if IS_OPEN.swap(true, Ordering::AcqRel) {
+            return Err(MMError::AlreadyOpen);
+        }
+
+        if let Err(err) = crate::open_port_cancellable(timeout, cancel) {
+            IS_OPEN.store(false, Ordering::Release);
+            return Err(err);
+        }
+
+        Ok(Self {
+            _not_sync: PhantomData,
+        })
+    }
+
+    /// Closes port.
+    pub fn close(self) -> Result<(), MMError> {
+        let res = crate::close_port();
+        IS_OPEN.store(false, Ordering::Release);
+        std::mem::forget(self);
+
+        res
+    }
+
+    /// Closes the port, waits `delay`, and opens it again with `timeout` in seconds, see [`open`](Self::open).
+    ///
+    /// Use it after [`reset_modem`](Self::reset_modem). Errors of closing the dropped port are ignored.
+    pub fn reopen(&self, delay: Duration, timeout: u64) -> Result<(), MMError> {
+        crate::reopen_port(delay, timeout)
+    }
+
+    /// Reads list of Marvelmind<sup>&copy;</sup> devices known to modem.
+    /// The list includes list of all devices connected by radio to modem’s network, including sleeping devices.
+    pub fn get_device_list(&self) -> Result<DeviceList, MMError> {
+        crate::get_device_list()
+    }
+
+    /// Reads list of Marvelmind<sup>&copy;</sup> devices like [`get_device_list`](Self::get_device_list),
+    /// retrying on `MMError::CommunicationError` until `timeout` expires, e.g. right after plugging in the modem.
+    ///
+    /// Other errors are returned at once: a `MMError::LicenseError` will not go away by retrying.
+    /// The error of the last attempt is returned on timeout.
+    pub fn get_device_list_with_retry(&self, timeout: Duration) -> Result<DeviceList, MMError> {
+        crate::get_device_list_with_retry(timeout)
+    }
+
+    /// See [`DeviceList::update_last_locations`].
+    pub fn update_last_locations(&self, device_list: &mut DeviceList) -> Result<Vec<u8>, MMError> {
+        device_list.update_last_locations(self)
+    }
+
+    /// Wakes the Marvelmind<sup>&copy;</sup> device with the given address.
+    ///
+    /// The device wakes asynchronously on the radio side, so `is_sleeping` will not flip
+    /// until the next call of `get_device_list`.
+    pub fn wake_device(&self, address: u8) -> Result<(), MMError> {
+        crate::wake_device(address)
+    }
+
+    /// Sends the Marvelmind<sup>&copy;</sup> device with the given address to sleep.
+    ///
+    /// The address is not checked against the modem's own, use [`DeviceList::sleep_devices`]
+    /// which refuses the addresses of the modems in the list with `MMError::InvalidAddress`.
+    ///
+    /// Like [`wake_device`](Self::wake_device), the request is handled asynchronously on the radio side:
+    /// call `sleep_device`, then refresh with `get_device_list` and observe `is_sleeping()` flip.
+    pub fn sleep_device(&self, address: u8) -> Result<(), MMError> {
+        crate::sleep_device(address)
+    }
+
+    /// Reads the full firmware version and CPU ID of the Marvelmind<sup>&copy;</sup> device with the given address.
+    pub fn get_device_version_and_id(&self, address: u8) -> Result<DeviceIdentity, MMError> {
+        crate::get_device_version_and_id(address)
+    }
+
+    /// Resets the Marvelmind<sup>&copy;</sup> device with the given address, like a power cycle.
+    ///
+    /// The device drops off the network while it restarts.
+    pub fn reset_device(&self, address: u8) -> Result<(), MMError> {
+        crate::reset_device(address)
+    }
+
+    /// Resets the locally connected Marvelmind<sup>&copy;</sup> modem, found with `get_device_list`.
+    ///
+    /// The serial port usually drops while the modem restarts, reopen it with [`reopen`](Self::reopen).
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use marvelmind::{Connection, MMError};
+    /// use std::time::Duration;
+    ///
+    /// let connection = Connection::open(30).unwrap();
+    ///
+    /// let mut devices_list = connection.get_device_list().unwrap();
+    ///
+    /// loop {
+    ///     match devices_list.update_last_locations(&connection) {
+    ///         Err(MMError::CommunicationError) => {
+    ///             connection.reset_modem().unwrap();
+    ///             connection.reopen(Duration::from_secs(3), 30).unwrap();
+    ///             devices_list = connection.get_device_list().unwrap();
+    ///         }
+    ///         res => {
+    ///             res.unwrap();
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn reset_modem(&self) -> Result<(), MMError> {
+        crate::reset_modem()
+    }
+
+    /// Restores the default settings of the Marvelmind<sup>&copy;</sup> device with the given address,
+    /// beacon or modem.
+    ///
+    /// Unlike [`erase_map`](Self::erase_map), this only affects the given device.
+    ///
+    /// **Warning:** on some firmware this also resets the radio profile and channel,
+    /// so the device may drop off the network and not come back. See [`set_default_settings_verified`](Self::set_default_settings_verified).
+    pub fn set_default_settings(&self, address: u8) -> Result<(), MMError> {
+        crate::set_default_settings(address)
+    }
+
+    /// Restores the default settings like [`set_default_settings`](Self::set_default_settings),
+    /// then reads the devices list until the device reappears connected or `timeout` expires.
+    ///
+    /// Right after the reset, the modem still lists the device as connected. The device is only considered back
+    /// once it has dropped out of the connected devices, or after `reboot_delay`,
+    /// usually [`DEFAULT_SETTINGS_REBOOT_DELAY`](crate::DEFAULT_SETTINGS_REBOOT_DELAY).
+    ///
+    /// Return `true` if the device reappeared.
+    pub fn set_default_settings_verified(
+        &self,
+        address: u8,
+        reboot_delay: Duration,
+        timeout: Duration,
+    ) -> Result<bool, MMError> {
+        crate::set_default_settings_verified(address, reboot_delay, timeout)
+    }
+
+    /// Sends user payload to the Marvelmind<sup>&copy;</sup> device with the given address, relayed by the modem.
+    ///
+    /// Return `MMError::PayloadTooLarge` if `data` is larger than `MAX_PAYLOAD_SIZE`.
+    pub fn send_to_device(&self, address: u8, data: &[u8]) -> Result<(), MMError> {
+        crate::send_to_device(address, data)
+    }
+
+    /// Sends user payload of any size up to 255 chunks to the Marvelmind<sup>&copy;</sup> device with the given address,
+    /// split into packets of `MAX_PAYLOAD_SIZE`, see [`send_to_device`](Self::send_to_device).
+    ///
+    /// Each packet starts with a header of the chunk index and the number of chunks, 1 byte each,
+    /// followed by up to `MAX_PAYLOAD_SIZE - CHUNK_HEADER_SIZE` bytes of `data`, so the receiver can reassemble it.
+    /// Empty `data` is sent as a single chunk with no data.
+    ///
+    /// Return `MMError::PayloadTooLarge` if `data` needs more than 255 chunks, nothing is sent then.
+    /// If a packet fails to send, the error is returned and the remaining packets are not sent.
+    pub fn send_to_device_chunked(&self, address: u8, data: &[u8]) -> Result<(), MMError> {
+        crate::send_to_device_chunked(address, data)
+    }
+
+    /// Reads user payload received from the Marvelmind<sup>&copy;</sup> device with the given address.
+    pub fn get_from_device(&self, address: u8) -> Result<Vec<u8>, MMError> {
+        crate::get_from_device(address)
+    }
+
+    /// Sets the coordinates of the stationary Marvelmind<sup>&copy;</sup> beacon with the given address, mm.
+    ///
+    /// Use it to enter coordinates surveyed by other means instead of the ultrasonic self-ranging.
+    /// The submaps of the beacon usually need to be frozen afterwards, see [`freeze_submap`](Self::freeze_submap),
+    /// otherwise the next self-ranging overwrites the coordinates.
+    pub fn set_beacon_location(
+        &self,
+        address: u8,
+        x_mm: i32,
+        y_mm: i32,
+        z_mm: i32,
+    ) -> Result<(), MMError> {
+        crate::set_beacon_location(address, x_mm, y_mm, z_mm)
+    }
+
+    /// Sets the coordinates of the stationary Marvelmind<sup>&copy;</sup> beacon with the given address,
+    /// see [`set_beacon_location`](Self::set_beacon_location).
+    ///
+    /// Use [`Coordinate::from_meters`] for coordinates in meters.
+    pub fn set_beacon_coordinate(
+        &self,
+        address: u8,
+        coordinate: Coordinate,
+    ) -> Result<(), MMError> {
+        crate::set_beacon_coordinate(address, coordinate)
+    }
+
+    /// Reads the mounting height of the stationary Marvelmind<sup>&copy;</sup> beacon with the given address, mm.
+    ///
+    /// The height is relative to the map origin, and is negative below it.
+    pub fn get_beacon_height(&self, address: u8) -> Result<i32, MMError> {
+        crate::get_beacon_height(address)
+    }
+
+    /// Writes the mounting height of the stationary Marvelmind<sup>&copy;</sup> beacon with the given address, mm.
+    ///
+    /// The height is relative to the map origin, and is negative below it.
+    /// It is required by 2D submaps, together with `SubmapSettings::hedges_height_2d_cm`.
+    pub fn set_beacon_height(&self, address: u8, height_mm: i32) -> Result<(), MMError> {
+        crate::set_beacon_height(address, height_mm)
+    }
+
+    /// Reads the last locations of every Marvelmind<sup>&copy;</sup> device in the packet, without a devices list,
+    /// e.g. for beacons which appeared after `get_device_list`.
+    ///
+    /// Each location is returned as a new device with its address, head index, coordinates, quality and status.
+    /// The type and firmware version are not in the packet, so the type is `DeviceType::Unknown(0)`
+    /// and the firmware version is zero.
+    /// Return an empty vector if the last locations are not new.
+    pub fn get_all_locations(&self) -> Result<Vec<Device>, MMError> {
+        crate::get_all_locations()
+    }
+
+    /// Reads the last location of the Marvelmind<sup>&copy;</sup> device with the given address,
+    /// without a devices list.
+    ///
+    /// Return `None` if the last locations are not new, or the device is not among them.
+    /// The last locations are consumed like by [`DeviceList::update_last_locations`],
+    /// so do not mix both for the same devices.
+    pub fn get_device_location(&self, address: u8) -> Result<Option<LocationFix>, MMError> {
+        crate::get_device_location(address)
+    }
+
+    /// Reads the last measured ultrasonic distances between pairs of Marvelmind<sup>&copy;</sup> beacons.
+    /// Pairs without a valid measurement are filtered out.
+    pub fn get_last_distances(&self) -> Result<Vec<Distance>, MMError> {
+        crate::get_last_distances()
+    }
+
+    /// Erases the map of the modem.
+    ///
+    /// This is destructive: all submaps and the coordinates of all beacons are lost.
+    /// The devices stay known to the modem, so `get_device_list` still works afterwards.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use marvelmind::{Connection, EraseConfirm};
+    ///
+    /// let connection = Connection::open(30).unwrap();
+    ///
+    /// connection.erase_map(EraseConfirm::Yes).unwrap();
+    /// connection.add_submap(0).unwrap();
+    ///
+    /// let device_list = connection.get_device_list().unwrap();
+    /// println!("{}", device_list);
+    /// ```
+    pub fn erase_map(&self, confirm: EraseConfirm) -> Result<(), MMError> {
+        crate::erase_map(confirm)
+    }
+
+    /// Creates a submap with the given id in the map of the modem.
+    ///
+    /// This is the first step of building a map from code, the submap is then configured by the submap settings.
+    pub fn add_submap(&self, submap_id: u8) -> Result<(), MMError> {
+        crate::add_submap(submap_id)
+    }
+
+    /// Deletes the submap with the given id from the map of the modem.
+    ///
+    /// The submap settings are read first, so deleting a submap which does not exist fails with the error of
+    /// [`get_submap_settings`](Self::get_submap_settings), and deleting a frozen submap fails with `MMError::SubmapFrozen`.
+    pub fn delete_submap(&self, submap_id: u8) -> Result<(), MMError> {
+        crate::delete_submap(submap_id)
+    }
+
+    /// Deletes every existing submap, see [`delete_submap`](Self::delete_submap).
+    ///
+    /// The existing submaps are found by reading the settings of each id up to `MAX_SUBMAP_ID`, which takes a while.
+    /// Return the result of each existing submap, keyed by id.
+    pub fn delete_all_submaps(&self) -> HashMap<u8, Result<(), MMError>> {
+        crate::delete_all_submaps()
+    }
+
+    /// Deletes every existing submap like [`delete_all_submaps`](Self::delete_all_submaps), stopping once `cancel` is cancelled.
+    ///
+    /// Return the result of each existing submap handled before the cancellation, keyed by id.
+    pub fn delete_all_submaps_cancellable(
+        &self,
+        cancel: &CancellationToken,
+    ) -> HashMap<u8, Result<(), MMError>> {
+        crate::delete_all_submaps_cancellable(cancel)
+    }
+
+    /// Freezes the submap with the given id, keeping the coordinates of its beacons fixed.
+    ///
+    /// Freeze the submap after the beacons finished self-ranging and the map converged.
+    pub fn freeze_submap(&self, submap_id: u8) -> Result<(), MMError> {
+        crate::freeze_submap(submap_id)
+    }
+
+    /// Unfreezes the submap with the given id, letting its beacons range again.
+    pub fn unfreeze_submap(&self, submap_id: u8) -> Result<(), MMError> {
+        crate::unfreeze_submap(submap_id)
+    }
+
+    /// Reads the settings of the submap with the given id.
+    pub fn get_submap_settings(&self, submap_id: u8) -> Result<SubmapSettings, MMError> {
+        crate::get_submap_settings(submap_id)
+    }
+
+    /// Writes the settings of the submap with the given id.
+    ///
+    /// The settings are validated before calling the api, returning `MMError::InvalidArgument` if out of range,
+    /// or if the starting beacon or a beacon of the starting set, when set, is not a beacon of the submap.
+    ///
+    /// The reserved bytes and the service zone point slots after the used points are written back
+    /// as read by [`get_submap_settings`](Self::get_submap_settings), so writing unchanged settings sends the same bytes that were read.
+    pub fn set_submap_settings(
+        &self,
+        submap_id: u8,
+        settings: &SubmapSettings,
+    ) -> Result<(), MMError> {
+        crate::set_submap_settings(submap_id, settings)
+    }
+
+    /// Reads the location update rate setting of the system, Hz.
+    pub fn get_update_rate(&self) -> Result<f32, MMError> {
+        crate::get_update_rate()
+    }
+
+    /// Writes the location update rate setting of the system, Hz.
+    ///
+    /// Return `MMError::InvalidArgument` if `hz` is out of `MIN_UPDATE_RATE..=MAX_UPDATE_RATE`,
+    /// instead of letting the modem silently clamp it.
+    /// The modem applies the change asynchronously, see [`set_update_rate_verified`](Self::set_update_rate_verified).
+    ///
+    /// Polling `update_last_locations` faster than the update rate does no harm,
+    /// the calls in between simply report no updated devices.
+    pub fn set_update_rate(&self, hz: f32) -> Result<(), MMError> {
+        crate::set_update_rate(hz)
+    }
+
+    /// Writes the location update rate setting like [`set_update_rate`](Self::set_update_rate),
+    /// then reads it back until it is applied or `timeout` expires.
+    ///
+    /// Return `true` if the read-back update rate matches `hz`.
+    pub fn set_update_rate_verified(&self, hz: f32, timeout: Duration) -> Result<bool, MMError> {
+        crate::set_update_rate_verified(hz, timeout)
+    }
+
+    /// Reads the air temperature setting used to compensate the speed of ultrasound, °C.
+    pub fn get_air_temperature(&self) -> Result<i8, MMError> {
+        crate::get_air_temperature()
+    }
+
+    /// Writes the air temperature setting used to compensate the speed of ultrasound, °C.
+    ///
+    /// Return `MMError::InvalidArgument` if `celsius` is out of `MIN_AIR_TEMPERATURE..=MAX_AIR_TEMPERATURE`.
+    ///
+    /// The modem keeps the setting until it is written again, so it only needs to be refreshed as the
+    /// temperature drifts. The speed of ultrasound changes about 0.17% per °C, so refreshing on every
+    /// degree of change is enough, see [`spawn_air_temperature_updater`](crate::spawn_air_temperature_updater).
+    pub fn set_air_temperature(&self, celsius: i8) -> Result<(), MMError> {
+        crate::set_air_temperature(celsius)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Connection {
+    /// Async counterpart of [`open`](Self::open), with the timeout as `Duration`.
+    ///
+    /// Each opening attempt runs on a `spawn_blocking` thread and the task waits between attempts without blocking,
+    /// so dropping the future stops retrying after the attempt in flight.
+    ///
+    /// Return `MMError::AlreadyOpen` if another connection is open.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use marvelmind::Connection;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut connection = Connection::open_async(Duration::from_secs(30)).await.unwrap();
+    ///     let device_list = connection.get_device_list_async().await.unwrap();
+    ///     println!("{}", device_list);
+    ///     connection.close_async().await.unwrap();
+    /// }
+    /// ```
+    pub async fn open_async(timeout: Duration) -> Result<Self, MMError> {
+        if IS_OPEN.swap(true, Ordering::AcqRel) {
+            return Err(MMError::AlreadyOpen);
+        }
+
+        // the future can be dropped while opening
+        let guard = OpeningGuard;
+        crate::open_port_async(timeout).await?;
+        std::mem::forget(guard);
+
+        Ok(Self {
+            _not_sync: PhantomData,
+        })
+    }
+
+    /// Async counterpart of [`close`](Self::close), running on a `spawn_blocking` thread.
+    pub async fn close_async(self) -> Result<(), MMError> {
+        let res = crate::close_port_async().await;
+        IS_OPEN.store(false, Ordering::Release);
+        std::mem::forget(self);
+
+        res
+    }
+
+    /// Async counterpart of [`get_device_list`](Self::get_device_list), running on a `spawn_blocking` thread.
+    ///
+    /// The connection is borrowed mutably, so no other call can be made while the blocking one is in flight.
+    pub async fn get_device_list_async(&mut self) -> Result<DeviceList, MMError> {
+        crate::get_device_list_async().await
+    }
+}
+
+/// Clears the open flag when dropped, unless forgotten once the port is open.
+#[cfg(feature = "tokio")]
+struct OpeningGuard;
+
+#[cfg(feature = "tokio")]
+impl Drop for OpeningGuard {
+    fn drop(&mut self) {
+        IS_OPEN.store(false, Ordering::Release);
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = crate::close_port();
+        IS_OPEN.store(false, Ordering::Release);
+    }
+}
@@ -0,0 +1,107 @@
+// Copyright 2025 wyzdwdz <wyzdwdz@gmail.com>
+//
+// Licensed under the MIT license <LICENSE or https://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to
+// those terms.
+
+//! Location logs in CSV
+//!
+//! Each row is `address,x,y,z,q,t`, with coordinates in mm and quality in %.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use marvelmind::{csv::{self, TimeFormat}, Connection};
+//! use std::fs::File;
+//!
+//! let connection = Connection::open(30).unwrap();
+//!
+//! let mut devices_list = connection.get_device_list().unwrap();
+//! let mut outfile = File::create("log.csv").unwrap();
+//! csv::write_header(&mut outfile).unwrap();
+//!
+//! loop {
+//!     let updated = devices_list.update_last_locations(&connection).unwrap();
+//!
+//!     for device in devices_list.devices() {
+//!         if updated.contains(&device.address()) {
+//!             csv::write_device_row(&mut outfile, device, TimeFormat::UnixMillis).unwrap();
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::{
+    io::{self, Write},
+    time::SystemTime,
+};
+
+use crate::Device;
+
+/// Format of the time column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeFormat {
+    /// Milliseconds since the unix epoch, e.g. `1735787045678`
+    #[default]
+    UnixMillis,
+    /// ISO 8601 in UTC, e.g. `2025-01-02T03:04:05.678Z`
+    Iso8601,
+}
+
+/// Write the header row.
+pub fn write_header(w: &mut impl Write) -> io::Result<()> {
+    writeln!(w, "address,x,y,z,q,t")
+}
+
+/// Write the row of a device, with its update time in `time_format`.
+pub fn write_device_row(w: &mut impl Write, d: &Device, time_format: TimeFormat) -> io::Result<()> {
+    let millis = d
+        .update_time()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64);
+
+    write!(
+        w,
+        "{},{},{},{},{},",
+        d.address(),
+        d.x(),
+        d.y(),
+        d.z(),
+        d.q()
+    )?;
+
+    match time_format {
+        TimeFormat::UnixMillis => writeln!(w, "{}", millis),
+        TimeFormat::Iso8601 => {
+            let secs = millis / 1000;
+            let (year, month, day) = civil_from_days((secs / 86400) as i64);
+
+            writeln!(
+                w,
+                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+                year,
+                month,
+                day,
+                secs % 86400 / 3600,
+                secs % 3600 / 60,
+                secs % 60,
+                millis % 1000
+            )
+        }
+    }
+}
+
+// Gregorian date of days since the unix epoch, from Howard Hinnant's date algorithms.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
@@ -0,0 +1,177 @@
+// Copyright 2025 wyzdwdz <wyzdwdz@gmail.com>
+//
+// Licensed under the MIT license <LICENSE or https://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to
+// those terms.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::SystemTime,
+};
+
+use crate::{Coordinate, DeviceList};
+
+/// Moving average of the last locations of each device
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use marvelmind::{Connection, PositionFilter};
+///
+/// let connection = Connection::open(30).unwrap();
+///
+/// let mut devices_list = connection.get_device_list().unwrap();
+/// let mut filter = PositionFilter::new(8);
+///
+/// loop {
+///     devices_list.update_last_locations(&connection).unwrap();
+///     filter.ingest(&devices_list);
+///
+///     println!("{:?}", filter.smoothed(10));
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PositionFilter {
+    depth: usize,
+    fixes: HashMap<u8, VecDeque<Coordinate>>,
+    update_times: HashMap<u8, SystemTime>,
+}
+
+impl PositionFilter {
+    /// Create a filter averaging the last `depth` locations of each device, at least 1.
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth: depth.max(1),
+            fixes: HashMap::new(),
+            update_times: HashMap::new(),
+        }
+    }
+
+    /// Add the locations of the devices list updated since the last call.
+    ///
+    /// Locations with zero quality are dropped.
+    /// If more than 1 device has the same address, only the first one in the list is used.
+    pub fn ingest(&mut self, device_list: &DeviceList) {
+        for (address, device) in device_list.by_address_map() {
+            if device.q == 0 {
+                continue;
+            }
+
+            if self.update_times.get(&address) == Some(&device.update_time) {
+                continue;
+            }
+            self.update_times.insert(address, device.update_time);
+
+            let fixes = self.fixes.entry(address).or_default();
+            if fixes.len() == self.depth {
+                fixes.pop_front();
+            }
+            fixes.push_back(device.coordinate());
+        }
+    }
+
+    /// Get the average of the last locations of the device with the given address, mm.
+    ///
+    /// Return `None` if no location of the device has been added.
+    pub fn smoothed(&self, address: u8) -> Option<Coordinate> {
+        let fixes = self.fixes.get(&address)?;
+        let count = fixes.len() as i64;
+
+        let (x, y, z) = fixes.iter().fold((0_i64, 0_i64, 0_i64), |(x, y, z), fix| {
+            (x + fix.x as i64, y + fix.y as i64, z + fix.z as i64)
+        });
+
+        Some(Coordinate {
+            x: (x / count) as i32,
+            y: (y / count) as i32,
+            z: (z / count) as i32,
+        })
+    }
+
+    /// Forget the locations of every device.
+    pub fn clear(&mut self) {
+        self.fixes.clear();
+        self.update_times.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Device, DeviceFlags, DeviceType, LocationStatus};
+    use std::time::Duration;
+
+    /// Devices list of the hedgehog 10 located at `x` with quality `q`, `secs` after the epoch.
+    fn located(x: i32, q: u8, secs: u64) -> DeviceList {
+        let device = Device {
+            address: 10,
+            head_index: 0,
+            is_duplicated: false,
+            is_sleeping: false,
+            v_major: 7,
+            v_minor: 0,
+            v_second: 0,
+            dtype: DeviceType::SuperBeaconHedgedog,
+            is_connected: true,
+            x,
+            y: 0,
+            z: 0,
+            q,
+            location_status: LocationStatus::default(),
+            update_time: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+            flags: DeviceFlags(1),
+        };
+
+        DeviceList::from_devices([device], false).unwrap()
+    }
+
+    #[test]
+    fn locations_are_averaged_over_the_depth() {
+        let mut filter = PositionFilter::new(2);
+        assert_eq!(filter.smoothed(10), None);
+
+        filter.ingest(&located(1000, 100, 1));
+        assert_eq!(filter.smoothed(10).unwrap().x, 1000);
+        filter.ingest(&located(2000, 100, 2));
+        assert_eq!(filter.smoothed(10).unwrap().x, 1500);
+        filter.ingest(&located(4000, 100, 3));
+        assert_eq!(filter.smoothed(10).unwrap().x, 3000);
+    }
+
+    #[test]
+    fn zero_quality_locations_are_skipped() {
+        let mut filter = PositionFilter::new(4);
+
+        filter.ingest(&located(0, 0, 1));
+        assert_eq!(filter.smoothed(10), None);
+
+        filter.ingest(&located(1000, 100, 2));
+        filter.ingest(&located(9000, 0, 3));
+        assert_eq!(filter.smoothed(10).unwrap().x, 1000);
+    }
+
+    #[test]
+    fn a_location_is_added_once() {
+        let mut filter = PositionFilter::new(3);
+
+        let device_list = located(1000, 100, 1);
+        filter.ingest(&device_list);
+        filter.ingest(&device_list);
+        filter.ingest(&located(3000, 100, 2));
+
+        assert_eq!(filter.smoothed(10).unwrap().x, 2000);
+    }
+
+    #[test]
+    fn clear_forgets_every_location() {
+        let mut filter = PositionFilter::new(2);
+        filter.ingest(&located(1000, 100, 1));
+
+        filter.clear();
+        assert_eq!(filter.smoothed(10), None);
+
+        // the same location is added again after clearing
+        filter.ingest(&located(1000, 100, 1));
+        assert_eq!(filter.smoothed(10).unwrap().x, 1000);
+    }
+}
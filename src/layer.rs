@@ -0,0 +1,34 @@
+// Copyright 2025 wyzdwdz <wyzdwdz@gmail.com>
+//
+// Licensed under the MIT license <LICENSE or https://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to
+// those terms.
+
+use crate::{Connection, Device, DeviceList, MMError};
+
+/// Source of the devices list and the last locations of Marvelmind<sup>&copy;</sup> devices
+///
+/// [`Connection`] reads them from the api library. With the `mock` feature,
+/// `mock::MockDeviceLayer` serves predetermined ones, so code taking a `&dyn DeviceLayer`
+/// can be tested without hardware.
+///
+/// See [`DeviceList::update_last_locations_with`] and [`DeviceListBuilder::build_with`](crate::DeviceListBuilder::build_with).
+pub trait DeviceLayer {
+    /// Read the devices list, like [`Connection::get_device_list`].
+    fn get_device_list(&self) -> Result<DeviceList, MMError>;
+
+    /// Read the last locations as new devices, like [`Connection::get_all_locations`].
+    ///
+    /// Return an empty vector if the last locations are not new.
+    fn get_all_locations(&self) -> Result<Vec<Device>, MMError>;
+}
+
+impl DeviceLayer for Connection {
+    fn get_device_list(&self) -> Result<DeviceList, MMError> {
+        Connection::get_device_list(self)
+    }
+
+    fn get_all_locations(&self) -> Result<Vec<Device>, MMError> {
+        Connection::get_all_locations(self)
+    }
+}
@@ -8,19 +8,19 @@
 //!
 //! # Example
 //!
-//! ```rust
-//! use marvelmind as mm;
+//! ```rust,no_run
+//! use marvelmind::{self as mm, Connection};
 //!
 //! let version = mm::api_version().unwrap();
 //! println!("api version: {}", version);
 //!
-//! mm::open_port(30).unwrap();
+//! let connection = Connection::open(30).unwrap();
 //! println!("open port successfully");
 //!
-//! let mut devices_list = mm::get_device_list().unwrap();
-//! let _ = devices_list.update_last_locations().unwrap();
+//! let mut devices_list = connection.get_device_list().unwrap();
+//! let _ = devices_list.update_last_locations(&connection).unwrap();
 //!
-//! let devices = device_list.devices();
+//! let devices = devices_list.devices();
 //! for device in devices {
 //!     println!(
 //!         "address #{:0>3} x {:.3} y {:.3} z {:.3} q {}",
@@ -34,16 +34,97 @@
 //! ```
 
 use std::{
+    collections::{HashMap, HashSet},
     fmt, mem,
-    thread::sleep,
-    time::{self, Instant, SystemTime},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver},
+        Arc,
+    },
+    thread::{self, sleep, JoinHandle},
+    time::{self, Duration, Instant, SystemTime},
 };
 use zerocopy::{
-    byteorder::little_endian::{I32, U16, U32},
-    FromBytes,
+    byteorder::little_endian::{I16, I32, U16, U32},
+    FromBytes, FromZeros, IntoBytes,
 };
-use zerocopy_derive::{FromBytes, Immutable, KnownLayout, Unaligned};
+use zerocopy_derive::{FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned};
 
+#[cfg(all(feature = "log", feature = "tracing"))]
+compile_error!("features `log` and `tracing` are mutually exclusive");
+
+// only used with a logging feature, by `traced`
+#[cfg(any(feature = "log", feature = "tracing"))]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::trace!($($arg)*);
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*);
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::debug!($($arg)*);
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+        #[cfg(not(any(feature = "log", feature = "tracing")))]
+        let _ = format_args!($($arg)*);
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::info!($($arg)*);
+        #[cfg(feature = "tracing")]
+        tracing::info!($($arg)*);
+        #[cfg(not(any(feature = "log", feature = "tracing")))]
+        let _ = format_args!($($arg)*);
+    };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::warn!($($arg)*);
+        #[cfg(feature = "tracing")]
+        tracing::warn!($($arg)*);
+        #[cfg(not(any(feature = "log", feature = "tracing")))]
+        let _ = format_args!($($arg)*);
+    };
+}
+
+mod builder;
+mod clock;
+mod connection;
+#[cfg(feature = "csv")]
+pub mod csv;
+mod filter;
+mod layer;
+#[cfg(feature = "mock")]
+pub mod mock;
+mod payload;
+mod poller;
+mod reader;
+mod tracker;
+
+pub use builder::DeviceListBuilder;
+pub use clock::DeviceClock;
+pub use connection::Connection;
+pub use filter::PositionFilter;
+pub use layer::DeviceLayer;
+pub use payload::{PayloadPacket, PayloadReceiver};
+pub use poller::{LocationPoller, LocationUpdate, PollerHandle};
+pub use reader::LocationsReader;
+pub use tracker::{CallbackPanic, SubscriptionId, Tracker};
+
+#[cfg(feature = "mock")]
+use mock::ffi::*;
+
+#[cfg(not(feature = "mock"))]
 #[cfg_attr(target_os = "windows", link(name = "dashapi", kind = "raw-dylib"))]
 #[cfg_attr(not(target_os = "windows"), link(name = "dashapi"))]
 unsafe extern "C" {
@@ -53,6 +134,40 @@ unsafe extern "C" {
     fn mm_close_port() -> bool;
     fn mm_get_devices_list(pdata: *mut [u8; mem::size_of::<MMDeviceList>()]) -> bool;
     fn mm_get_last_locations2(pdata: *mut [u8; mem::size_of::<MMLastLocations>()]) -> bool;
+    fn mm_wake_device(address: u8) -> bool;
+    fn mm_send_to_sleep_device(address: u8) -> bool;
+    fn mm_get_device_version_and_id(
+        address: u8,
+        pdata: *mut [u8; mem::size_of::<MMDeviceVersion>()],
+    ) -> bool;
+    fn mm_reset_device(address: u8) -> bool;
+    fn mm_set_default_settings(address: u8) -> bool;
+    fn mm_send_to_device(address: u8, pdata: *const [u8; mem::size_of::<MMPayload>()]) -> bool;
+    fn mm_get_from_device(address: u8, pdata: *mut [u8; mem::size_of::<MMPayload>()]) -> bool;
+    fn mm_set_beacon_location(
+        address: u8,
+        pdata: *const [u8; mem::size_of::<MMBeaconLocation>()],
+    ) -> bool;
+    fn mm_get_beacon_height(address: u8, pdata: *mut I32) -> bool;
+    fn mm_set_beacon_height(address: u8, pdata: *mut I32) -> bool;
+    fn mm_get_update_rate_setting(pdata: *mut U32) -> bool;
+    fn mm_set_update_rate_setting(pdata: *mut U32) -> bool;
+    fn mm_erase_map() -> bool;
+    fn mm_add_submap(submap_id: u8) -> bool;
+    fn mm_delete_submap(submap_id: u8) -> bool;
+    fn mm_freeze_submap(submap_id: u8) -> bool;
+    fn mm_unfreeze_submap(submap_id: u8) -> bool;
+    fn mm_get_submap_settings(
+        submap_id: u8,
+        pdata: *mut [u8; mem::size_of::<MMSubmapSettings>()],
+    ) -> bool;
+    fn mm_set_submap_settings(
+        submap_id: u8,
+        pdata: *const [u8; mem::size_of::<MMSubmapSettings>()],
+    ) -> bool;
+    fn mm_get_air_temperature(pdata: *mut i8) -> bool;
+    fn mm_set_air_temperature(pdata: *mut i8) -> bool;
+    fn mm_get_last_distances(pdata: *mut [u8; mem::size_of::<MMLastDistances>()]) -> bool;
 }
 
 /// Marvelmind<sup>&copy;</sup> api call error
@@ -66,10 +181,52 @@ pub enum MMError {
     LicenseError,
     /// Unknown error type
     UnknownError,
+    /// Error code not known by this crate, e.g. from a newer api library
+    UnknownCode(u32),
+    /// The address is not valid for this operation
+    InvalidAddress(u8),
+    /// More than 1 device with the same address
+    DuplicateAddress(u8),
+    /// An argument is out of the supported range
+    InvalidArgument(&'static str),
+    /// The submap is frozen
+    SubmapFrozen(u8),
+    /// The operation was cancelled
+    Cancelled,
+    /// The payload is larger than `MAX_PAYLOAD_SIZE`, with its size
+    PayloadTooLarge(usize),
+    /// A `Connection` is already open
+    AlreadyOpen,
+    /// The device with this address is sleeping
+    DeviceSleeping(u8),
+    /// Some devices of a bulk call failed, with the error of each, keyed by address
+    PartialFailure(HashMap<u8, MMError>),
 }
 
 impl std::error::Error for MMError {}
 
+impl MMError {
+    /// Get the error code of the api library, `None` for the errors detected by this crate
+    /// and for `UnknownError`, which has no code.
+    ///
+    /// ```rust
+    /// use marvelmind::MMError;
+    ///
+    /// assert_eq!(MMError::SerialPortError.raw_code(), Some(2));
+    /// assert_eq!(MMError::UnknownCode(42).raw_code(), Some(42));
+    /// assert_eq!(MMError::InvalidAddress(0).raw_code(), None);
+    /// ```
+    pub fn raw_code(&self) -> Option<u32> {
+        match *self {
+            Self::CommunicationError => Some(1),
+            Self::SerialPortError => Some(2),
+            Self::LicenseError => Some(3),
+            Self::UnknownCode(code) => Some(code),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for MMError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -77,19 +234,124 @@ impl fmt::Display for MMError {
             Self::SerialPortError => write!(f, "error opening serial port",),
             Self::LicenseError => write!(f, "license is required"),
             Self::UnknownError => write!(f, "unknown error"),
+            Self::UnknownCode(code) => write!(f, "unknown error code: {}", code),
+            Self::InvalidAddress(address) => write!(f, "invalid device address: {}", address),
+            Self::DuplicateAddress(address) => write!(f, "duplicated device address: {}", address),
+            Self::InvalidArgument(msg) => write!(f, "invalid argument: {}", msg),
+            Self::SubmapFrozen(submap_id) => write!(f, "submap {} is frozen", submap_id),
+            Self::Cancelled => write!(f, "operation cancelled"),
+            Self::PayloadTooLarge(size) => write!(
+                f,
+                "payload of {} bytes exceeds {} bytes",
+                size, MAX_PAYLOAD_SIZE
+            ),
+            Self::AlreadyOpen => write!(f, "connection already open"),
+            Self::DeviceSleeping(address) => write!(f, "device is sleeping: {}", address),
+            Self::PartialFailure(ref failures) => {
+                let mut addresses: Vec<_> = failures.keys().collect();
+                addresses.sort();
+                write!(f, "devices failed: {:?}", addresses)
+            }
         }
     }
 }
 
+/// Convert to `std::io::Error` with the `MMError` as its inner error, so `?` works in `std::io` code.
+///
+/// | `MMError` | `ErrorKind` |
+/// |-----------|-------------|
+/// | `CommunicationError` | `ConnectionReset` |
+/// | `SerialPortError` | `NotFound` |
+/// | `LicenseError` | `PermissionDenied` |
+/// | `InvalidAddress`, `DuplicateAddress`, `InvalidArgument`, `PayloadTooLarge` | `InvalidInput` |
+/// | `Cancelled` | `Interrupted` |
+/// | `AlreadyOpen` | `AlreadyExists` |
+/// | other errors | `Other` |
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use marvelmind::{Connection, Device};
+/// use std::io::{self, Write};
+///
+/// fn log_device(connection: &Connection, device: &Device) -> io::Result<()> {
+///     let height_mm = connection.get_beacon_height(device.address())?;
+///
+///     let mut stdout = io::stdout().lock();
+///     writeln!(stdout, "{}: {} mm", device.address(), height_mm)?;
+///
+///     Ok(())
+/// }
+/// ```
+impl From<MMError> for std::io::Error {
+    fn from(err: MMError) -> Self {
+        use std::io::ErrorKind;
+
+        let kind = match err {
+            MMError::CommunicationError => ErrorKind::ConnectionReset,
+            MMError::SerialPortError => ErrorKind::NotFound,
+            MMError::LicenseError => ErrorKind::PermissionDenied,
+            MMError::InvalidAddress(_)
+            | MMError::DuplicateAddress(_)
+            | MMError::InvalidArgument(_)
+            | MMError::PayloadTooLarge(_) => ErrorKind::InvalidInput,
+            MMError::Cancelled => ErrorKind::Interrupted,
+            MMError::AlreadyOpen => ErrorKind::AlreadyExists,
+            MMError::UnknownError
+            | MMError::UnknownCode(_)
+            | MMError::SubmapFrozen(_)
+            | MMError::DeviceSleeping(_)
+            | MMError::PartialFailure(_) => ErrorKind::Other,
+        };
+
+        Self::new(kind, err)
+    }
+}
+
+/// Token to cancel long-running operations from another thread
+///
+/// Cancellation is only checked between api calls, an api call in flight always finishes.
+///
+/// | Operation | Cancellation |
+/// |-----------|--------------|
+/// | [`Connection::open_cancellable`] | between opening attempts, returns `MMError::Cancelled` |
+/// | [`Connection::delete_all_submaps_cancellable`] | between submaps, returns the results of the submaps handled so far |
+/// | `Connection::open_async` (`async` feature) | by dropping the future, between opening attempts |
+/// | [`LocationPoller`] | by [`LocationPoller::stop`], between polls |
+/// | [`PollerHandle`] | by [`PollerHandle::stop`] or dropping the handle, between polls |
+/// | [`spawn_air_temperature_updater`] | between sensor readings, the thread exits |
+/// | other operations | a single api call, not cancellable |
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token which is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the operations using this token or its clones.
+    #[inline]
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// If the token is cancelled.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
 struct MMDeviceList {
     num: u8,
     devices: [MMDevice; u8::MAX as usize + 1],
 }
 
 #[repr(C)]
-#[derive(Debug, FromBytes, Immutable, Unaligned)]
+#[derive(Debug, FromBytes, IntoBytes, Immutable, Unaligned)]
 struct MMDevice {
     address: u8,
     is_duplicated: u8,
@@ -104,290 +366,2602 @@ struct MMDevice {
 
 #[repr(C)]
 #[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+struct MMDeviceVersion {
+    v_major: u8,
+    v_minor: u8,
+    v_second: u8,
+    type_id: u8,
+    firmware_option: u8,
+    cpu_id: [u8; 6],
+    _tbd: [u8; 5],
+}
+
+#[repr(C)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
 struct MMLastLocations {
     coordinates: [MMCoordinate; 6],
-    _is_new: u8,
+    is_new: u8,
     _tbd: [u8; 5],
-    _size_payload: u8,
-    _payload: [u8; u8::MAX as usize + 1],
+    size_payload: u8,
+    payload: [u8; u8::MAX as usize + 1],
 }
 
 #[repr(C)]
-#[derive(Debug, FromBytes, Immutable, Unaligned)]
+#[derive(Debug, FromBytes, IntoBytes, Immutable, Unaligned)]
 struct MMCoordinate {
     address: u8,
-    _head_index: u8,
+    head_index: u8,
     x: I32,
     y: I32,
     z: I32,
-    _status_flag: u8,
+    status_flag: u8,
     q: u8,
     _tbd0: u8,
     _tbd1: u8,
     _tbd2: U16,
 }
 
+#[repr(C)]
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+struct MMLastDistances {
+    num: u8,
+    distances: [MMDistance; 16],
+}
+
+#[repr(C)]
+#[derive(Debug, FromBytes, Immutable, Unaligned)]
+struct MMDistance {
+    address_rx: u8,
+    _head_rx: u8,
+    address_tx: u8,
+    _head_tx: u8,
+    distance: U32,
+    _tbd: u8,
+}
+
+#[repr(C)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+struct MMPayload {
+    size_payload: u8,
+    payload: [u8; u8::MAX as usize + 1],
+}
+
+#[repr(C)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+struct MMSubmapSettings {
+    starting_beacon: u8,
+    starting_set: [u8; 4],
+    enabled_3d: u8,
+    only_for_z: u8,
+    limitation_distance_is_manual: u8,
+    max_distance_manual_m: u8,
+    shift_x_cm: I16,
+    shift_y_cm: I16,
+    shift_z_cm: I16,
+    rotation_cdeg: U16,
+    plane_quaternion: [I16; 4],
+    service_zone_thickness_cm: I16,
+    hedges_height_2d_cm: I16,
+    frozen: u8,
+    locked: u8,
+    beacons_higher: u8,
+    mirrored: u8,
+    beacons: [u8; 4],
+    nearby_submaps: [u8; 8],
+    service_zone_points_num: u8,
+    service_zone_points: [MMPoint; 8],
+    reserved: [u8; 16],
+}
+
+#[repr(C)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+struct MMBeaconLocation {
+    x: I32,
+    y: I32,
+    z: I32,
+}
+
+#[repr(C)]
+#[derive(Debug, FromBytes, IntoBytes, Immutable, Unaligned)]
+struct MMPoint {
+    x: I16,
+    y: I16,
+}
+
 /// Marvelmind<sup>&copy;</sup> devices list
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DeviceList {
     devices: Vec<Device>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    last_raw_payload: Option<Vec<u8>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    last_is_new: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    index: HashMap<u8, usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    min_q: u8,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    history: Option<PositionFilter>,
 }
 
 impl DeviceList {
+    /// Build a devices list from the given devices.
+    ///
+    /// If `allow_duplicates` is `false`, return `MMError::DuplicateAddress` when more than 1 device has the same address
+    /// and head index;
+    /// otherwise, the devices sharing an address are marked as duplicated like the modem does.
+    pub fn from_devices<I>(devices: I, allow_duplicates: bool) -> Result<Self, MMError>
+    where
+        I: IntoIterator<Item = Device>,
+    {
+        let mut devices: Vec<Device> = devices.into_iter().collect();
+
+        for idx in 0..devices.len() {
+            let address = devices[idx].address;
+            let head_index = devices[idx].head_index;
+            let is_duplicated = devices.iter().enumerate().any(|(other, device)| {
+                other != idx && device.address == address && device.head_index == head_index
+            });
+
+            if is_duplicated {
+                if !allow_duplicates {
+                    return Err(MMError::DuplicateAddress(address));
+                }
+                devices[idx].is_duplicated = true;
+            }
+        }
+
+        Ok(DeviceList {
+            devices,
+            last_raw_payload: None,
+            last_is_new: false,
+            index: HashMap::new(),
+            min_q: 0,
+            history: None,
+        })
+    }
+
     /// Get Marvelmind<sup>&copy;</sup> devices information.
     #[inline]
     pub fn devices(&self) -> &Vec<Device> {
         &self.devices
     }
 
-    /// Update the last locations of each Marvelmind<sup>&copy;</sup> device.
+    /// Get the devices keyed by address.
     ///
-    /// If one of locations is updated, return `true`; otherwise, return `false`.
-    pub fn update_last_locations(&mut self) -> Result<bool, MMError> {
-        let mut pdata = [0 as u8; mem::size_of::<MMLastLocations>()];
-        let update_time = SystemTime::now();
-        let res = unsafe { mm_get_last_locations2(&mut pdata) };
+    /// If more than 1 device has the same address, e.g. the heads of a multi-head beacon, the first one in the list is kept.
+    pub fn by_address_map(&self) -> HashMap<u8, &Device> {
+        let mut map = HashMap::<u8, &Device>::new();
 
-        if res == false {
-            return Err(get_last_error());
+        for device in &self.devices {
+            map.entry(device.address).or_insert(device);
         }
 
-        let mut is_update = false;
+        map
+    }
 
-        let last_locations = MMLastLocations::ref_from_bytes(&pdata).unwrap();
+    /// Compare the devices list with a previous snapshot of it, by address.
+    ///
+    /// If more than 1 device has the same address, only the first one in the list is compared.
+    pub fn diff(&self, previous: &DeviceList) -> Vec<DeviceChange> {
+        let previous_map = previous.by_address_map();
+        let current_map = self.by_address_map();
 
-        for device in &mut self.devices {
-            let coord = &last_locations.coordinates;
-            for idx in 0..coord.len() {
-                if coord[idx].address == device.address && coord[idx].q <= 100 {
-                    device.x = coord[idx].x.into();
-                    device.y = coord[idx].y.into();
-                    device.z = coord[idx].z.into();
-                    device.q = coord[idx].q;
-                    device.update_time = update_time;
-                    is_update = true;
-                }
+        let mut changes = Vec::<DeviceChange>::new();
+        let mut visited = HashSet::<u8>::new();
+
+        for device in &self.devices {
+            if !visited.insert(device.address) {
+                continue;
+            }
+
+            let Some(old) = previous_map.get(&device.address) else {
+                changes.push(DeviceChange::NewDevice(device.clone()));
+                continue;
+            };
+
+            if old.coordinate() != device.coordinate() {
+                changes.push(DeviceChange::PositionChanged {
+                    address: device.address,
+                    old: old.coordinate(),
+                    new: device.coordinate(),
+                });
+            }
+
+            if old.is_sleeping != device.is_sleeping || old.is_connected != device.is_connected {
+                changes.push(DeviceChange::StatusChanged {
+                    address: device.address,
+                });
+            }
+        }
+
+        for device in &previous.devices {
+            if !current_map.contains_key(&device.address) && visited.insert(device.address) {
+                changes.push(DeviceChange::DeviceGone(device.address));
             }
         }
 
-        Ok(is_update)
+        changes
     }
-}
 
-/// The information of Marvelmind<sup>&copy;</sup> device
-#[derive(Debug, Clone)]
-pub struct Device {
-    address: u8,
-    is_duplicated: bool,
-    is_sleeping: bool,
-    v_major: u8,
-    v_minor: u8,
-    v_second: u8,
-    dtype: DeviceType,
-    is_connected: bool,
-    x: i32,
-    y: i32,
-    z: i32,
-    q: u8,
-    update_time: SystemTime,
-}
+    /// Build the cached address index used by [`DeviceList::get`].
+    ///
+    /// The index is cleared whenever the devices of the list change, e.g. a new head of a multi-head beacon is added.
+    pub fn build_index(&mut self) {
+        self.index.clear();
 
-impl Device {
-    /// Get the address of Marvelmind<sup>&copy;</sup> device.
-    #[inline]
-    pub fn address(&self) -> u8 {
-        self.address
+        for (idx, device) in self.devices.iter().enumerate() {
+            self.index.entry(device.address).or_insert(idx);
+        }
     }
 
-    /// If the address of device is duplicated - more than 1 device with same address was found.
-    #[inline]
-    pub fn is_duplicated(&self) -> bool {
-        self.is_duplicated
+    /// Get the device with the given address, the first one if more than 1 device has the same address.
+    ///
+    /// The lookup uses the cached index if built by [`DeviceList::build_index`]; otherwise, it searches the list.
+    pub fn get(&self, address: u8) -> Option<&Device> {
+        match self.index.is_empty() {
+            true => self.devices.iter().find(|device| device.address == address),
+            false => self.index.get(&address).map(|&idx| &self.devices[idx]),
+        }
     }
 
-    /// If the device is sleeping.
-    #[inline]
-    pub fn is_sleeping(&self) -> bool {
-        self.is_sleeping
+    /// Get a copy of the devices keyed by address.
+    ///
+    /// If more than 1 device has the same address, e.g. the heads of a multi-head beacon, the first one in the list is kept,
+    /// like [`DeviceList::get`] and [`DeviceList::by_address_map`].
+    pub fn to_map(&self) -> HashMap<u8, Device> {
+        self.by_address_map()
+            .into_iter()
+            .map(|(address, device)| (address, device.clone()))
+            .collect()
     }
 
-    /// Get major version of firmware (example: "6", for version V6.07a).
+    /// Get the user payload carried by the last read locations, e.g. data sent from the hedgehog's UART.
+    ///
+    /// Return `None` if the last locations carried no payload.
     #[inline]
-    pub fn v_major(&self) -> u8 {
-        self.v_major
+    pub fn last_payload(&self) -> Option<&[u8]> {
+        self.last_raw_payload
+            .as_deref()
+            .filter(|payload| !payload.is_empty())
     }
 
-    /// Get minor version of firmware (example: "7", for version V6.07a).
+    /// Get the payload field of the last read locations as read from the api, `size_payload` bytes,
+    /// e.g. to parse protocol extensions.
+    ///
+    /// Unlike [`DeviceList::last_payload`], an empty payload is `Some`.
+    /// Return `None` if the locations were never read from the api.
     #[inline]
-    pub fn v_minor(&self) -> u8 {
-        self.v_minor
+    pub fn last_raw_payload(&self) -> Option<&[u8]> {
+        self.last_raw_payload.as_deref()
     }
 
-    /// Get second minor version of firmware (example: "1", for version V6.07a).
-    #[inline]
-    pub fn v_second(&self) -> u8 {
-        self.v_second
+    /// Ask every sleeping device of the list to wake up, see [`Connection::wake_device`].
+    ///
+    /// The device wakes asynchronously on the radio side, so `is_sleeping` will not flip
+    /// until the next call of [`Connection::get_device_list`].
+    ///
+    /// A failure does not stop the other devices from being asked.
+    /// Return the addresses of the devices which were asked to wake, or `MMError::PartialFailure`
+    /// with the error of each device which failed.
+    pub fn wake_all_sleeping(&self, connection: &Connection) -> Result<Vec<u8>, MMError> {
+        let mut addresses = Vec::<u8>::new();
+        let mut failures = HashMap::<u8, MMError>::new();
+
+        for device in self.sleeping_devices() {
+            match connection.wake_device(device.address) {
+                Ok(()) => addresses.push(device.address),
+                Err(err) => {
+                    failures.insert(device.address, err);
+                }
+            }
+        }
+
+        match failures.is_empty() {
+            true => Ok(addresses),
+            false => Err(MMError::PartialFailure(failures)),
+        }
     }
 
-    /// Get Marvelmind<sup>&copy;</sup> device type.
-    #[inline]
-    pub fn dtype(&self) -> DeviceType {
-        self.dtype.clone()
+    /// Read the identity of each connected Marvelmind<sup>&copy;</sup> device, see [`Connection::get_device_version_and_id`].
+    ///
+    /// Sleeping devices are not queried, and are recorded with `MMError::DeviceSleeping`.
+    /// Other disconnected devices are left out.
+    /// Return the result of each device, keyed by address.
+    pub fn audit_firmware(
+        &self,
+        connection: &Connection,
+    ) -> HashMap<u8, Result<DeviceIdentity, MMError>> {
+        let mut results = HashMap::<u8, Result<DeviceIdentity, MMError>>::new();
+
+        for device in &self.devices {
+            let res = match (device.is_sleeping, device.is_connected) {
+                (true, _) => Err(MMError::DeviceSleeping(device.address)),
+                (false, true) => connection.get_device_version_and_id(device.address),
+                (false, false) => continue,
+            };
+
+            results.entry(device.address).or_insert(res);
+        }
+
+        results
     }
 
-    /// If the device has confirmed connection.
-    #[inline]
-    pub fn is_connected(&self) -> bool {
-        self.is_connected
+    /// Send the devices with the given addresses to sleep, see [`Connection::sleep_device`].
+    ///
+    /// Addresses of modems in the list are refused with `MMError::InvalidAddress`.
+    /// Like [`DeviceList::wake_all_sleeping`], a failure does not stop the other devices.
+    /// Return the addresses of the devices which were sent to sleep, or `MMError::PartialFailure`
+    /// with the error of each device which failed.
+    pub fn sleep_devices<I>(
+        &self,
+        _connection: &Connection,
+        addresses: I,
+    ) -> Result<Vec<u8>, MMError>
+    where
+        I: IntoIterator<Item = u8>,
+    {
+        let mut slept = Vec::<u8>::new();
+        let mut failures = HashMap::<u8, MMError>::new();
+
+        for address in addresses {
+            let is_modem = self.modems().any(|device| device.address == address);
+
+            let res = match is_modem {
+                true => Err(MMError::InvalidAddress(address)),
+                false => sleep_device(address),
+            };
+
+            match res {
+                Ok(()) => slept.push(address),
+                Err(err) => {
+                    failures.insert(address, err);
+                }
+            }
+        }
+
+        match failures.is_empty() {
+            true => Ok(slept),
+            false => Err(MMError::PartialFailure(failures)),
+        }
     }
 
-    /// Get X coordinate, mm.
-    #[inline]
-    pub fn x(&self) -> i32 {
-        self.x
+    /// Set the coordinates of stationary beacons, see [`Connection::set_beacon_location`].
+    ///
+    /// Addresses not in the list are refused with `MMError::InvalidAddress`, unless `allow_unknown`
+    /// is set, e.g. for beacons which are asleep while surveying.
+    /// Return the errors of the beacons which failed, keyed by address.
+    pub fn set_beacon_locations(
+        &self,
+        connection: &Connection,
+        locations: &HashMap<u8, Coordinate>,
+        allow_unknown: bool,
+    ) -> HashMap<u8, MMError> {
+        let mut failures = HashMap::<u8, MMError>::new();
+
+        for (&address, &coordinate) in locations {
+            let is_known = self.devices.iter().any(|device| device.address == address);
+
+            let res = match is_known || allow_unknown {
+                true => connection.set_beacon_coordinate(address, coordinate),
+                false => Err(MMError::InvalidAddress(address)),
+            };
+
+            if let Err(err) = res {
+                failures.insert(address, err);
+            }
+        }
+
+        failures
     }
 
-    /// Get Y coordinate, mm.
+    /// Get the minimum quality of the locations applied by the updates, 0...100%, see [`DeviceListBuilder::with_quality_threshold`].
     #[inline]
-    pub fn y(&self) -> i32 {
-        self.y
+    pub fn min_q(&self) -> u8 {
+        self.min_q
     }
 
-    /// Get Z coordinate, mm.
+    /// Get the moving average of the locations applied by the updates, see [`DeviceListBuilder::with_history_depth`].
+    ///
+    /// Return `None` if the list was built without history.
     #[inline]
-    pub fn z(&self) -> i32 {
-        self.z
+    pub fn history(&self) -> Option<&PositionFilter> {
+        self.history.as_ref()
     }
 
-    /// Get Quality of positioning, 0...100%.
+    /// If the modem reported the last read locations as new.
     #[inline]
-    pub fn q(&self) -> u8 {
-        self.q
+    pub fn last_is_new(&self) -> bool {
+        self.last_is_new
     }
 
-    /// Get the time information when updating location of the device.
-    #[inline]
-    pub fn update_time(&self) -> SystemTime {
-        self.update_time
+    /// Get the beacons (not in hedgehog mode) of the devices list.
+    pub fn beacons(&self) -> impl Iterator<Item = &Device> {
+        self.devices
+            .iter()
+            .filter(|device| device.dtype.is_beacon())
     }
-}
 
-/// Marvelmind<sup>&copy;</sup> device type
-#[derive(Debug, Clone, PartialEq)]
-pub enum DeviceType {
-    /// Beacon HW V4.5
-    BeaconHwV45,
-    /// Beacon HW V4.5 (hedgehog mode)
-    BeaconHwV45Hedgehog,
-    /// Modem HW V4.9
-    ModemHwV49,
-    /// Beacon HW V4.9
-    BeaconHwV49,
-    /// Beacon HW V4.9 (hedgehog mode)
-    BeaconHwV49Hedgehog,
-    /// Beacon Mini-RX
-    BeaconMiniRx,
-    /// Beacon Mini-TX
-    BeaconMiniTx,
-    /// Beacon-TX-IP67
-    BeaconTxIp67,
-    /// Beacon industrial-RX
-    BeaconIndustrialRx,
-    /// Super-Beacon
-    SuperBeacon,
-    /// Super-Beacon (hedgehog mode)
-    SuperBeaconHedgedog,
-    /// Industrial Super-Beacon
-    IndustrialSuperBeacon,
-    /// Industrial Super-Beacon (hedgehog mode)
-    IndustrialSuperBeaconHedgedog,
-    /// Super-Modem
-    SuperModem,
-    /// Modem HW V5.1
-    ModemHwV51,
-}
+    /// Get the modems of the devices list.
+    pub fn modems(&self) -> impl Iterator<Item = &Device> {
+        self.devices.iter().filter(|device| device.dtype.is_modem())
+    }
 
-impl TryFrom<u8> for DeviceType {
-    type Error = &'static str;
+    /// Get the modem of the devices list, `None` if there is none.
+    ///
+    /// If the list has more than 1 modem, e.g. with duplicated addresses, the first one in the list is returned.
+    pub fn modem(&self) -> Option<&Device> {
+        self.modems().next()
+    }
 
-    fn try_from(id: u8) -> Result<Self, Self::Error> {
-        match id {
-            22 => Ok(Self::BeaconHwV45),
-            23 => Ok(Self::BeaconHwV45Hedgehog),
-            24 => Ok(Self::ModemHwV49),
-            30 => Ok(Self::BeaconHwV49),
-            31 => Ok(Self::BeaconHwV49Hedgehog),
-            32 => Ok(Self::BeaconMiniRx),
-            36 => Ok(Self::BeaconMiniTx),
-            37 => Ok(Self::BeaconTxIp67),
-            41 => Ok(Self::BeaconIndustrialRx),
-            42 => Ok(Self::SuperBeacon),
-            43 => Ok(Self::SuperBeaconHedgedog),
-            44 => Ok(Self::IndustrialSuperBeacon),
-            45 => Ok(Self::IndustrialSuperBeaconHedgedog),
-            46 => Ok(Self::SuperModem),
-            48 => Ok(Self::ModemHwV51),
-            _ => Err("Unspecific device type id"),
-        }
+    /// Get the beacons in hedgehog mode of the devices list.
+    pub fn hedgehogs(&self) -> impl Iterator<Item = &Device> {
+        self.devices
+            .iter()
+            .filter(|device| device.dtype.is_hedgehog())
     }
-}
 
-fn get_last_error() -> MMError {
-    let mut err_id: U32 = U32::ZERO;
-    let res = unsafe { mm_get_last_error(&mut err_id) };
+    /// Get the sleeping devices of the devices list.
+    pub fn sleeping_devices(&self) -> impl Iterator<Item = &Device> {
+        self.devices.iter().filter(|device| device.is_sleeping)
+    }
 
-    match res {
-        true => match u32::from(err_id) {
-            1 => MMError::CommunicationError,
-            2 => MMError::SerialPortError,
-            3 => MMError::LicenseError,
-            _ => MMError::UnknownError,
-        },
-        false => MMError::UnknownError,
+    /// Get the devices with confirmed connection of the devices list.
+    pub fn connected_devices(&self) -> impl Iterator<Item = &Device> {
+        self.devices.iter().filter(|device| device.is_connected)
     }
-}
+
+    /// Get the devices which are connected, awake and have a location with nonzero quality.
+    fn located_devices(&self) -> impl Iterator<Item = &Device> {
+        self.devices
+            .iter()
+            .filter(|device| device.is_connected && !device.is_sleeping && device.q > 0)
+    }
+
+    /// Get the average coordinate of the connected, awake devices with nonzero quality, mm.
+    ///
+    /// Return `None` if there is no such device.
+    pub fn centroid(&self) -> Option<Coordinate> {
+        let mut count = 0_i64;
+        let (mut x, mut y, mut z) = (0_i64, 0_i64, 0_i64);
+
+        for device in self.located_devices() {
+            count += 1;
+            x += device.x as i64;
+            y += device.y as i64;
+            z += device.z as i64;
+        }
+
+        if count == 0 {
+            return None;
+        }
+
+        Some(Coordinate {
+            x: (x / count) as i32,
+            y: (y / count) as i32,
+            z: (z / count) as i32,
+        })
+    }
+
+    /// Get the connected, awake device with nonzero quality nearest to `point`.
+    ///
+    /// Of devices at the same distance, the one with the lower address is returned.
+    /// Return `None` if there is no such device.
+    pub fn nearest_to(&self, point: &Coordinate) -> Option<&Device> {
+        self.located_devices()
+            .min_by_key(|device| (device.coordinate().distance_squared(point), device.address))
+    }
+
+    /// Get the connected, awake devices with nonzero quality within `radius_mm` of `center`.
+    pub fn within_radius<'a>(
+        &'a self,
+        center: &'a Coordinate,
+        radius_mm: u32,
+    ) -> impl Iterator<Item = &'a Device> {
+        let radius_squared = radius_mm as i64 * radius_mm as i64;
+
+        self.located_devices()
+            .filter(move |device| device.coordinate().distance_squared(center) <= radius_squared)
+    }
+
+    /// Get the axis-aligned bounding box `(min_corner, max_corner)` of the connected,
+    /// awake devices with nonzero quality, mm.
+    ///
+    /// Return `None` if there is no such device.
+    pub fn bounding_box(&self) -> Option<(Coordinate, Coordinate)> {
+        self.located_devices().fold(None, |bbox, device| {
+            let coordinate = device.coordinate();
+
+            let Some((min, max)) = bbox else {
+                return Some((coordinate, coordinate));
+            };
+
+            Some((
+                Coordinate {
+                    x: min.x.min(coordinate.x),
+                    y: min.y.min(coordinate.y),
+                    z: min.z.min(coordinate.z),
+                },
+                Coordinate {
+                    x: max.x.max(coordinate.x),
+                    y: max.y.max(coordinate.y),
+                    z: max.z.max(coordinate.z),
+                },
+            ))
+        })
+    }
+
+    /// Update the last locations of each Marvelmind<sup>&copy;</sup> device.
+    ///
+    /// Return the addresses of the devices which received fresh coordinates in this call,
+    /// each address appears only once. An empty vector means nothing was updated,
+    /// which is also the case when the modem reports that the locations are not new.
+    ///
+    /// Locations are matched by address and head index. When a new head of a known multi-head beacon reports,
+    /// a device entry for that head is added to the list.
+    ///
+    /// Every location with quality up to 100% is applied, including zero quality ones, which overwrite
+    /// a previously good location. Use [`DeviceList::update_last_locations_min_q`] to skip them,
+    /// or build the list with [`DeviceListBuilder::with_quality_threshold`].
+    pub fn update_last_locations(&mut self, _connection: &Connection) -> Result<Vec<u8>, MMError> {
+        self.read_last_locations(SystemTime::now(), &HashMap::new(), self.min_q, &[])
+            .map(|updated| addresses_of(&updated))
+    }
+
+    /// Update the last locations like [`DeviceList::update_last_locations`],
+    /// returning the updated `(address, head index)` pairs.
+    pub(crate) fn update_last_heads(&mut self) -> Result<Vec<(u8, u8)>, MMError> {
+        self.read_last_locations(SystemTime::now(), &HashMap::new(), self.min_q, &[])
+    }
+
+    /// Get the device with the given address and head index.
+    pub(crate) fn get_head(&self, address: u8, head_index: u8) -> Option<&Device> {
+        self.devices
+            .iter()
+            .find(|device| device.address == address && device.head_index == head_index)
+    }
+
+    /// Update the last locations of each Marvelmind<sup>&copy;</sup> device like [`DeviceList::update_last_locations`],
+    /// skipping the locations with quality below `min_q`, 0...100%.
+    pub fn update_last_locations_min_q(
+        &mut self,
+        _connection: &Connection,
+        min_q: u8,
+    ) -> Result<Vec<u8>, MMError> {
+        self.read_last_locations(SystemTime::now(), &HashMap::new(), min_q, &[])
+            .map(|updated| addresses_of(&updated))
+    }
+
+    /// Update the last locations of each Marvelmind<sup>&copy;</sup> device,
+    /// stamping the updated devices with `received_at` instead of the current time,
+    /// or with the time of their own fix in `measured_at`, keyed by address.
+    ///
+    /// Useful when the locations are replayed or forwarded and the meaningful time is the original capture time.
+    pub fn update_last_locations_at(
+        &mut self,
+        _connection: &Connection,
+        received_at: SystemTime,
+        measured_at: &HashMap<u8, SystemTime>,
+    ) -> Result<Vec<u8>, MMError> {
+        self.read_last_locations(received_at, measured_at, self.min_q, &[])
+            .map(|updated| addresses_of(&updated))
+    }
+
+    /// Update the last locations of each Marvelmind<sup>&copy;</sup> device like [`DeviceList::update_last_locations`],
+    /// reading them from `layer` instead of the api library.
+    ///
+    /// Like the api, at most 6 locations are used per read. The layer has no user payload,
+    /// so `last_payload` and `last_raw_payload` are cleared.
+    pub fn update_last_locations_with(
+        &mut self,
+        layer: &dyn DeviceLayer,
+    ) -> Result<Vec<u8>, MMError> {
+        let received_at = SystemTime::now();
+        let locations = layer.get_all_locations()?;
+
+        let mut last_locations = MMLastLocations::new_zeroed();
+        last_locations.is_new = (!locations.is_empty()).into();
+        for (coord, device) in last_locations.coordinates.iter_mut().zip(&locations) {
+            coord.address = device.address;
+            coord.head_index = device.head_index;
+            coord.x = device.x.into();
+            coord.y = device.y.into();
+            coord.z = device.z.into();
+            coord.q = device.q;
+            coord.status_flag = device.location_status.raw();
+        }
+
+        let updated = self.apply_locations(
+            &last_locations,
+            received_at,
+            &HashMap::new(),
+            self.min_q,
+            &[],
+        );
+        self.last_raw_payload = None;
+
+        Ok(addresses_of(&updated))
+    }
+
+    /// Update the last locations of the Marvelmind<sup>&copy;</sup> devices with the given addresses only,
+    /// like [`DeviceList::update_last_locations`]. Locations of other devices are ignored.
+    ///
+    /// If `addresses` is empty, every device is updated.
+    pub fn update_filtered(
+        &mut self,
+        _connection: &Connection,
+        addresses: &[u8],
+    ) -> Result<Vec<u8>, MMError> {
+        self.read_last_locations(SystemTime::now(), &HashMap::new(), self.min_q, addresses)
+            .map(|updated| addresses_of(&updated))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "update_last_locations",
+            skip(self, received_at, measured_at, addresses),
+            fields(updated)
+        )
+    )]
+    fn read_last_locations(
+        &mut self,
+        received_at: SystemTime,
+        measured_at: &HashMap<u8, SystemTime>,
+        min_q: u8,
+        addresses: &[u8],
+    ) -> Result<Vec<(u8, u8)>, MMError> {
+        let mut pdata = [0_u8; mem::size_of::<MMLastLocations>()];
+        let res = traced("mm_get_last_locations2", || unsafe {
+            mm_get_last_locations2(&mut pdata)
+        });
+
+        if !res {
+            let err = get_last_error();
+            warn!("get last locations failed: {}", err);
+            return Err(err);
+        }
+
+        let last_locations = MMLastLocations::ref_from_bytes(&pdata).unwrap();
+        let updated =
+            self.apply_locations(last_locations, received_at, measured_at, min_q, addresses);
+
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("updated", tracing::field::debug(&updated));
+
+        Ok(updated)
+    }
+
+    /// Spawn a task polling the last locations every `interval`, publishing the devices list to a watch channel
+    /// whenever a location is updated. A slow receiver always sees the latest devices list, not a queue of old ones.
+    ///
+    /// The blocking api calls run on `spawn_blocking` threads, so this must be called within a tokio runtime.
+    /// The task stops when every receiver is dropped and gives `connection` back, or it can be cancelled
+    /// by aborting the returned handle, which closes the port.
+    #[cfg(feature = "tokio")]
+    pub fn into_watch_stream(
+        self,
+        connection: Connection,
+        interval: Duration,
+    ) -> (
+        tokio::sync::watch::Receiver<DeviceList>,
+        tokio::task::JoinHandle<Connection>,
+    ) {
+        let (tx, rx) = tokio::sync::watch::channel(self.clone());
+        let mut device_list = self;
+        let mut connection = connection;
+
+        let handle = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+
+                // the connection moves along, so it is not closed under the call in flight if the task is aborted
+                let polled = tokio::task::spawn_blocking(move || {
+                    let res = device_list.update_last_locations(&connection);
+                    (connection, device_list, res)
+                })
+                .await;
+                let res;
+                (connection, device_list, res) = match polled {
+                    Ok(polled) => polled,
+                    Err(err) => std::panic::resume_unwind(err.into_panic()),
+                };
+
+                if let Ok(updated) = res {
+                    if !updated.is_empty() && tx.send(device_list.clone()).is_err() {
+                        return connection;
+                    }
+                }
+            }
+        });
+
+        (rx, handle)
+    }
+
+    fn apply_locations(
+        &mut self,
+        last_locations: &MMLastLocations,
+        received_at: SystemTime,
+        measured_at: &HashMap<u8, SystemTime>,
+        min_q: u8,
+        addresses: &[u8],
+    ) -> Vec<(u8, u8)> {
+        let mut updated = Vec::<(u8, u8)>::new();
+
+        let size_payload = last_locations.size_payload as usize;
+        self.last_raw_payload = Some(last_locations.payload[..size_payload].to_vec());
+
+        self.last_is_new = last_locations.is_new != 0;
+
+        if !self.last_is_new {
+            return updated;
+        }
+
+        for coord in &last_locations.coordinates {
+            if coord.q > 100 || coord.q < min_q {
+                continue;
+            }
+
+            if !addresses.is_empty() && !addresses.contains(&coord.address) {
+                continue;
+            }
+
+            let idx = match self.devices.iter().position(|device| {
+                device.address == coord.address && device.head_index == coord.head_index
+            }) {
+                Some(idx) => idx,
+                None => {
+                    // another head of a known multi-head beacon
+                    let Some(device) = self
+                        .devices
+                        .iter()
+                        .find(|device| device.address == coord.address)
+                    else {
+                        continue;
+                    };
+
+                    let mut device = device.clone();
+                    device.head_index = coord.head_index;
+                    self.devices.push(device);
+                    self.index.clear();
+                    self.devices.len() - 1
+                }
+            };
+
+            let device = &mut self.devices[idx];
+            device.x = coord.x.into();
+            device.y = coord.y.into();
+            device.z = coord.z.into();
+            device.q = coord.q;
+            device.location_status = LocationStatus(coord.status_flag);
+            device.update_time = measured_at
+                .get(&coord.address)
+                .copied()
+                .unwrap_or(received_at);
+
+            debug!(
+                "location updated: address={} head={} x={} y={} z={} q={} status={:#04x}",
+                device.address,
+                device.head_index,
+                device.x,
+                device.y,
+                device.z,
+                device.q,
+                device.location_status.raw()
+            );
+
+            if !updated.contains(&(device.address, device.head_index)) {
+                updated.push((device.address, device.head_index));
+            }
+        }
+
+        if let Some(mut history) = self.history.take() {
+            history.ingest(self);
+            self.history = Some(history);
+        }
+
+        updated
+    }
+}
+
+/// Get the addresses of the updated `(address, head index)` pairs, each address once.
+fn addresses_of(updated: &[(u8, u8)]) -> Vec<u8> {
+    let mut addresses = Vec::<u8>::with_capacity(updated.len());
+
+    for &(address, _) in updated {
+        if !addresses.contains(&address) {
+            addresses.push(address);
+        }
+    }
+
+    addresses
+}
+
+/// The information of Marvelmind<sup>&copy;</sup> device
+///
+/// Devices are identified by address and head index: equality, hashing and ordering only compare those,
+/// so two snapshots of the same beacon compare equal even if it moved. Use `state_eq` to compare all the fields.
+///
+/// ```rust
+/// use marvelmind::Device;
+/// use std::collections::BTreeSet;
+///
+/// fn sorted_addresses(devices: &[Device]) -> Vec<u8> {
+///     let devices: BTreeSet<&Device> = devices.iter().collect();
+///     devices.into_iter().map(Device::address).collect()
+/// }
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Device {
+    address: u8,
+    head_index: u8,
+    is_duplicated: bool,
+    is_sleeping: bool,
+    v_major: u8,
+    v_minor: u8,
+    v_second: u8,
+    dtype: DeviceType,
+    is_connected: bool,
+    x: i32,
+    y: i32,
+    z: i32,
+    q: u8,
+    location_status: LocationStatus,
+    #[cfg_attr(feature = "serde", serde(with = "unix_millis"))]
+    update_time: SystemTime,
+    #[cfg_attr(feature = "serde", serde(default))]
+    flags: DeviceFlags,
+}
+
+impl Device {
+    /// Get the address of Marvelmind<sup>&copy;</sup> device.
+    #[inline]
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Get the index of the ultrasonic head, for beacons with multiple heads sharing one address.
+    #[inline]
+    pub fn head_index(&self) -> u8 {
+        self.head_index
+    }
+
+    /// If the address of device is duplicated - more than 1 device with same address was found.
+    #[inline]
+    pub fn is_duplicated(&self) -> bool {
+        self.is_duplicated
+    }
+
+    /// If the device is sleeping.
+    #[inline]
+    pub fn is_sleeping(&self) -> bool {
+        self.is_sleeping
+    }
+
+    /// Get major version of firmware (example: "6", for version V6.07a).
+    #[inline]
+    pub fn v_major(&self) -> u8 {
+        self.v_major
+    }
+
+    /// Get minor version of firmware (example: "7", for version V6.07a).
+    #[inline]
+    pub fn v_minor(&self) -> u8 {
+        self.v_minor
+    }
+
+    /// Get second minor version of firmware (example: "1", for version V6.07a).
+    #[inline]
+    pub fn v_second(&self) -> u8 {
+        self.v_second
+    }
+
+    /// Get the firmware version, displayed as "6.07.1".
+    #[inline]
+    pub fn firmware_version(&self) -> FirmwareVersion {
+        FirmwareVersion {
+            major: self.v_major,
+            minor: self.v_minor,
+            second: self.v_second,
+        }
+    }
+
+    /// Get the firmware version as `(major, minor, second)` for comparison (example: (6, 7, 1), for version V6.07a).
+    #[inline]
+    pub fn firmware_version_tuple(&self) -> (u8, u8, u8) {
+        (self.v_major, self.v_minor, self.v_second)
+    }
+
+    /// Get Marvelmind<sup>&copy;</sup> device type.
+    #[inline]
+    pub fn dtype(&self) -> DeviceType {
+        self.dtype
+    }
+
+    /// If the device has confirmed connection.
+    #[inline]
+    pub fn is_connected(&self) -> bool {
+        self.is_connected
+    }
+
+    /// Get X coordinate, mm.
+    #[inline]
+    pub fn x(&self) -> i32 {
+        self.x
+    }
+
+    /// Get Y coordinate, mm.
+    #[inline]
+    pub fn y(&self) -> i32 {
+        self.y
+    }
+
+    /// Get Z coordinate, mm.
+    #[inline]
+    pub fn z(&self) -> i32 {
+        self.z
+    }
+
+    /// Get the coordinate of the device, mm.
+    #[inline]
+    pub fn coordinate(&self) -> Coordinate {
+        Coordinate {
+            x: self.x,
+            y: self.y,
+            z: self.z,
+        }
+    }
+
+    /// Get Quality of positioning, 0...100%.
+    #[inline]
+    pub fn q(&self) -> u8 {
+        self.q
+    }
+
+    /// Get the raw flags of the device, including bits which are not decoded.
+    #[inline]
+    pub fn flags_raw(&self) -> u8 {
+        self.flags.raw()
+    }
+
+    /// Get the flags of the device.
+    #[inline]
+    pub fn flags(&self) -> DeviceFlags {
+        self.flags
+    }
+
+    /// Get the status of the last location of the device.
+    #[inline]
+    pub fn location_status(&self) -> LocationStatus {
+        self.location_status
+    }
+
+    /// Get the time information when updating location of the device.
+    ///
+    /// It is `SystemTime::UNIX_EPOCH` until the first location of the device is received.
+    #[inline]
+    pub fn update_time(&self) -> SystemTime {
+        self.update_time
+    }
+
+    /// Get the elapsed time since the last location of the device was received.
+    ///
+    /// Return `None` if no location has been received yet.
+    pub fn age(&self) -> Option<Duration> {
+        if self.update_time == SystemTime::UNIX_EPOCH {
+            return None;
+        }
+
+        Some(
+            SystemTime::now()
+                .duration_since(self.update_time)
+                .unwrap_or_default(),
+        )
+    }
+
+    /// If the last location of the device was received less than `max_age` ago.
+    ///
+    /// Return `false` if no location has been received yet.
+    pub fn is_fresh(&self, max_age: Duration) -> bool {
+        self.age().is_some_and(|age| age < max_age)
+    }
+
+    /// If all the fields of the devices are equal, including coordinates and update time.
+    pub fn state_eq(&self, other: &Device) -> bool {
+        self.address == other.address
+            && self.head_index == other.head_index
+            && self.is_duplicated == other.is_duplicated
+            && self.is_sleeping == other.is_sleeping
+            && self.v_major == other.v_major
+            && self.v_minor == other.v_minor
+            && self.v_second == other.v_second
+            && self.dtype == other.dtype
+            && self.is_connected == other.is_connected
+            && self.x == other.x
+            && self.y == other.y
+            && self.z == other.z
+            && self.q == other.q
+            && self.location_status == other.location_status
+            && self.update_time == other.update_time
+            && self.flags == other.flags
+    }
+}
+
+impl PartialEq for Device {
+    fn eq(&self, other: &Self) -> bool {
+        self.address == other.address && self.head_index == other.head_index
+    }
+}
+
+impl Eq for Device {}
+
+impl std::hash::Hash for Device {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.address.hash(state);
+        self.head_index.hash(state);
+    }
+}
+
+impl PartialOrd for Device {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Device {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.address, self.head_index).cmp(&(other.address, other.head_index))
+    }
+}
+
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = if self.is_sleeping {
+            "sleeping"
+        } else if self.is_connected {
+            "connected"
+        } else {
+            "disconnected"
+        };
+
+        write!(
+            f,
+            "Device #{:0>3} [{}] fw {} @ ({:.3}m, {:.3}m, {:.3}m) q={}% [{}]",
+            self.address,
+            self.dtype,
+            self.firmware_version(),
+            self.x as f64 / 1000.0,
+            self.y as f64 / 1000.0,
+            self.z as f64 / 1000.0,
+            self.q,
+            status
+        )
+    }
+}
+
+/// A change of a device between two snapshots of a devices list, see [`DeviceList::diff`]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeviceChange {
+    /// The coordinate of the device changed
+    PositionChanged {
+        /// Address of the device
+        address: u8,
+        /// Previous coordinate, mm
+        old: Coordinate,
+        /// Current coordinate, mm
+        new: Coordinate,
+    },
+    /// The device is not in the previous snapshot
+    NewDevice(Device),
+    /// The device with this address is not in the current snapshot
+    DeviceGone(u8),
+    /// The sleeping or connected status of the device changed
+    StatusChanged {
+        /// Address of the device
+        address: u8,
+    },
+}
+
+impl FromIterator<Device> for DeviceList {
+    /// Collect devices into a devices list, marking the devices sharing an address as duplicated.
+    fn from_iter<I: IntoIterator<Item = Device>>(iter: I) -> Self {
+        Self::from_devices(iter, true).unwrap()
+    }
+}
+
+impl fmt::Display for DeviceList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, device) in self.devices.iter().enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", device)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Firmware version of Marvelmind<sup>&copy;</sup> device
+///
+/// Displayed in the canonical form "6.07.1". The alternate form `{:#}` displays the Dashboard form "V6.07a",
+/// where the second minor version 1 is the suffix "a" and 0 is no suffix.
+///
+/// # Example
+///
+/// ```rust
+/// use marvelmind::FirmwareVersion;
+///
+/// let version = FirmwareVersion { major: 6, minor: 7, second: 1 };
+/// assert_eq!(version.to_string(), "6.07.1");
+/// assert_eq!(format!("{:#}", version), "V6.07a");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FirmwareVersion {
+    /// Major version (example: 6, for version V6.07a)
+    pub major: u8,
+    /// Minor version (example: 7, for version V6.07a)
+    pub minor: u8,
+    /// Second minor version (example: 1, for version V6.07a)
+    pub second: u8,
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !f.alternate() {
+            return write!(f, "{}.{:02}.{}", self.major, self.minor, self.second);
+        }
+
+        write!(f, "V{}.{:02}", self.major, self.minor)?;
+        match self.second {
+            0 => Ok(()),
+            1..=26 => write!(f, "{}", char::from(b'a' + self.second - 1)),
+            _ => write!(f, ".{}", self.second),
+        }
+    }
+}
+
+/// Version of the Marvelmind<sup>&copy;</sup> API library
+///
+/// Decoded from the `u32` returned by [`api_version_raw`], assuming the layout
+/// `0x00MMmmpp`: bits 16...23 are the major version, bits 8...15 the minor version
+/// and bits 0...7 the patch version. Bits 24...31 are ignored.
+/// The dashapi documentation does not specify the packing, compare `api_version_raw()` printed as hex
+/// with the version of your library to verify it.
+///
+/// # Example
+///
+/// ```rust
+/// use marvelmind::ApiVersion;
+///
+/// let version = ApiVersion::from(0x0007_0102);
+/// assert_eq!(version.to_string(), "7.1.2");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ApiVersion {
+    /// Major version
+    pub major: u8,
+    /// Minor version
+    pub minor: u8,
+    /// Patch version
+    pub patch: u8,
+}
+
+impl From<u32> for ApiVersion {
+    fn from(version: u32) -> Self {
+        Self {
+            major: (version >> 16) as u8,
+            minor: (version >> 8) as u8,
+            patch: version as u8,
+        }
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Velocity of Marvelmind<sup>&copy;</sup> device, mm/s
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Velocity {
+    /// X velocity, mm/s
+    pub vx: f64,
+    /// Y velocity, mm/s
+    pub vy: f64,
+    /// Z velocity, mm/s
+    pub vz: f64,
+}
+
+/// Coordinate of Marvelmind<sup>&copy;</sup> device, mm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Coordinate {
+    /// X coordinate, mm
+    pub x: i32,
+    /// Y coordinate, mm
+    pub y: i32,
+    /// Z coordinate, mm
+    pub z: i32,
+}
+
+impl Coordinate {
+    /// Get the squared distance to `other`, mm².
+    pub fn distance_squared(&self, other: &Coordinate) -> i64 {
+        let dx = self.x as i64 - other.x as i64;
+        let dy = self.y as i64 - other.y as i64;
+        let dz = self.z as i64 - other.z as i64;
+
+        dx * dx + dy * dy + dz * dz
+    }
+
+    /// Create a coordinate from coordinates in meters, rounded to mm.
+    pub fn from_meters(x: f64, y: f64, z: f64) -> Self {
+        Self {
+            x: (x * 1000.0).round() as i32,
+            y: (y * 1000.0).round() as i32,
+            z: (z * 1000.0).round() as i32,
+        }
+    }
+}
+
+/// Estimates the velocity of a device between two snapshots of it, e.g. from two cloned devices lists.
+///
+/// Return `None` if `curr` is not updated later than `prev`, or if the quality of either location is zero.
+pub fn velocity_between(prev: &Device, curr: &Device) -> Option<Velocity> {
+    if prev.q == 0 || curr.q == 0 {
+        return None;
+    }
+
+    let dt = curr.update_time.duration_since(prev.update_time).ok()?;
+    if dt.is_zero() {
+        return None;
+    }
+
+    let dt = dt.as_secs_f64();
+    Some(Velocity {
+        vx: (curr.x as f64 - prev.x as f64) / dt,
+        vy: (curr.y as f64 - prev.y as f64) / dt,
+        vz: (curr.z as f64 - prev.z as f64) / dt,
+    })
+}
+
+/// Status flags of Marvelmind<sup>&copy;</sup> device location
+///
+/// | Bit | Meaning |
+/// |-----|---------|
+/// | 0 | coordinates unavailable |
+///
+/// The api library does not document the status byte of the last locations. Bit 0 is decoded like the flags byte
+/// of the hedgehog coordinates packet in the Marvelmind<sup>&copy;</sup> interface protocol, where it marks unavailable
+/// coordinates. No documented bits mark frozen or extrapolated locations, read the other bits with `raw`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LocationStatus(u8);
+
+impl LocationStatus {
+    const UNAVAILABLE: u8 = 0b00000001;
+
+    /// Get the raw status flags, including bits which are not decoded.
+    #[inline]
+    pub fn raw(&self) -> u8 {
+        self.0
+    }
+
+    /// If the coordinates are unavailable, e.g. the device is out of the map.
+    #[inline]
+    pub fn is_unavailable(&self) -> bool {
+        self.0 & Self::UNAVAILABLE > 0
+    }
+}
+
+/// Flags of Marvelmind<sup>&copy;</sup> device in the devices list
+///
+/// | Bit | Meaning |
+/// |-----|---------|
+/// | 0 | connected |
+///
+/// Only bit 0 is decoded, as used by the devices list since the first version of this crate.
+/// The api library does not document the other bits, read them with `raw`.
+///
+/// # Example
+///
+/// ```rust
+/// use marvelmind::DeviceFlags;
+///
+/// let flags = DeviceFlags::from(0b1000_0001);
+/// assert!(flags.is_connected());
+/// assert_eq!(flags.raw(), 0b1000_0001);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceFlags(u8);
+
+impl DeviceFlags {
+    const CONNECTED: u8 = 0b00000001;
+
+    /// Get the raw flags, including bits which are not decoded.
+    #[inline]
+    pub fn raw(&self) -> u8 {
+        self.0
+    }
+
+    /// If the device is connected, i.e. the modem received at least one message from it.
+    #[inline]
+    pub fn is_connected(&self) -> bool {
+        self.0 & Self::CONNECTED > 0
+    }
+}
+
+impl From<u8> for DeviceFlags {
+    fn from(flags: u8) -> Self {
+        Self(flags)
+    }
+}
+
+/// Marvelmind<sup>&copy;</sup> device type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeviceType {
+    /// Beacon HW V4.5
+    BeaconHwV45,
+    /// Beacon HW V4.5 (hedgehog mode)
+    BeaconHwV45Hedgehog,
+    /// Modem HW V4.9
+    ModemHwV49,
+    /// Beacon HW V4.9
+    BeaconHwV49,
+    /// Beacon HW V4.9 (hedgehog mode)
+    BeaconHwV49Hedgehog,
+    /// Beacon Mini-RX
+    BeaconMiniRx,
+    /// Beacon Mini-TX
+    BeaconMiniTx,
+    /// Beacon-TX-IP67
+    BeaconTxIp67,
+    /// Beacon industrial-RX
+    BeaconIndustrialRx,
+    /// Super-Beacon
+    SuperBeacon,
+    /// Super-Beacon (hedgehog mode)
+    SuperBeaconHedgedog,
+    /// Industrial Super-Beacon
+    IndustrialSuperBeacon,
+    /// Industrial Super-Beacon (hedgehog mode)
+    IndustrialSuperBeaconHedgedog,
+    /// Super-Modem
+    SuperModem,
+    /// Modem HW V5.1
+    ModemHwV51,
+    /// Device type not known by this crate, with its raw type id
+    Unknown(u8),
+}
+
+impl DeviceType {
+    /// Get the numeric device type id used by the api.
+    pub fn type_id(&self) -> u8 {
+        match *self {
+            Self::BeaconHwV45 => 22,
+            Self::BeaconHwV45Hedgehog => 23,
+            Self::ModemHwV49 => 24,
+            Self::BeaconHwV49 => 30,
+            Self::BeaconHwV49Hedgehog => 31,
+            Self::BeaconMiniRx => 32,
+            Self::BeaconMiniTx => 36,
+            Self::BeaconTxIp67 => 37,
+            Self::BeaconIndustrialRx => 41,
+            Self::SuperBeacon => 42,
+            Self::SuperBeaconHedgedog => 43,
+            Self::IndustrialSuperBeacon => 44,
+            Self::IndustrialSuperBeaconHedgedog => 45,
+            Self::SuperModem => 46,
+            Self::ModemHwV51 => 48,
+            Self::Unknown(id) => id,
+        }
+    }
+
+    /// Get the hardware major version, taken from the device names which include it:
+    /// 4 for the HW V4.5 and V4.9 devices, 5 for the Modem HW V5.1.
+    ///
+    /// Return 0 for the other devices, whose names do not give their hardware version, and for `Unknown`.
+    pub fn hardware_generation(&self) -> u8 {
+        match *self {
+            Self::BeaconHwV45 => 4,
+            Self::BeaconHwV45Hedgehog => 4,
+            Self::ModemHwV49 => 4,
+            Self::BeaconHwV49 => 4,
+            Self::BeaconHwV49Hedgehog => 4,
+            Self::BeaconMiniRx => 0,
+            Self::BeaconMiniTx => 0,
+            Self::BeaconTxIp67 => 0,
+            Self::BeaconIndustrialRx => 0,
+            Self::SuperBeacon => 0,
+            Self::SuperBeaconHedgedog => 0,
+            Self::IndustrialSuperBeacon => 0,
+            Self::IndustrialSuperBeaconHedgedog => 0,
+            Self::SuperModem => 0,
+            Self::ModemHwV51 => 5,
+            Self::Unknown(_) => 0,
+        }
+    }
+
+    /// If the device is located with a Z coordinate, i.e. it is a beacon or a hedgehog.
+    ///
+    /// The last locations have X, Y and Z for every located device, whether the Z coordinate is computed
+    /// depends on the submap, see [`SubmapSettings::is_3d`]. Modems are not located.
+    /// Return `false` for `Unknown`.
+    pub fn supports_3d(&self) -> bool {
+        match *self {
+            Self::BeaconHwV45 => true,
+            Self::BeaconHwV45Hedgehog => true,
+            Self::ModemHwV49 => false,
+            Self::BeaconHwV49 => true,
+            Self::BeaconHwV49Hedgehog => true,
+            Self::BeaconMiniRx => true,
+            Self::BeaconMiniTx => true,
+            Self::BeaconTxIp67 => true,
+            Self::BeaconIndustrialRx => true,
+            Self::SuperBeacon => true,
+            Self::SuperBeaconHedgedog => true,
+            Self::IndustrialSuperBeacon => true,
+            Self::IndustrialSuperBeaconHedgedog => true,
+            Self::SuperModem => false,
+            Self::ModemHwV51 => false,
+            Self::Unknown(_) => false,
+        }
+    }
+
+    /// If the device has an onboard IMU.
+    ///
+    /// Return `true` for the Beacon HW V4.9 and the Super-Beacons, which Marvelmind<sup>&copy;</sup> sells
+    /// with IMU fusion. Return `false` for the other devices, whose IMU could not be confirmed, and for `Unknown`.
+    pub fn supports_imu(&self) -> bool {
+        match *self {
+            Self::BeaconHwV45 => false,
+            Self::BeaconHwV45Hedgehog => false,
+            Self::ModemHwV49 => false,
+            Self::BeaconHwV49 => true,
+            Self::BeaconHwV49Hedgehog => true,
+            Self::BeaconMiniRx => false,
+            Self::BeaconMiniTx => false,
+            Self::BeaconTxIp67 => false,
+            Self::BeaconIndustrialRx => false,
+            Self::SuperBeacon => true,
+            Self::SuperBeaconHedgedog => true,
+            Self::IndustrialSuperBeacon => true,
+            Self::IndustrialSuperBeaconHedgedog => true,
+            Self::SuperModem => false,
+            Self::ModemHwV51 => false,
+            Self::Unknown(_) => false,
+        }
+    }
+
+    /// If the device is a beacon (not in hedgehog mode).
+    pub fn is_beacon(&self) -> bool {
+        matches!(
+            self,
+            Self::BeaconHwV45
+                | Self::BeaconHwV49
+                | Self::BeaconMiniRx
+                | Self::BeaconMiniTx
+                | Self::BeaconTxIp67
+                | Self::BeaconIndustrialRx
+                | Self::SuperBeacon
+                | Self::IndustrialSuperBeacon
+        )
+    }
+
+    /// If the device is a modem.
+    pub fn is_modem(&self) -> bool {
+        matches!(self, Self::ModemHwV49 | Self::SuperModem | Self::ModemHwV51)
+    }
+
+    /// If the device is a beacon in hedgehog mode.
+    pub fn is_hedgehog(&self) -> bool {
+        matches!(
+            self,
+            Self::BeaconHwV45Hedgehog
+                | Self::BeaconHwV49Hedgehog
+                | Self::SuperBeaconHedgedog
+                | Self::IndustrialSuperBeaconHedgedog
+        )
+    }
+}
+
+impl fmt::Display for DeviceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::BeaconHwV45 => write!(f, "Beacon HW V4.5"),
+            Self::BeaconHwV45Hedgehog => write!(f, "Beacon HW V4.5 (hedgehog mode)"),
+            Self::ModemHwV49 => write!(f, "Modem HW V4.9"),
+            Self::BeaconHwV49 => write!(f, "Beacon HW V4.9"),
+            Self::BeaconHwV49Hedgehog => write!(f, "Beacon HW V4.9 (hedgehog mode)"),
+            Self::BeaconMiniRx => write!(f, "Beacon Mini-RX"),
+            Self::BeaconMiniTx => write!(f, "Beacon Mini-TX"),
+            Self::BeaconTxIp67 => write!(f, "Beacon-TX-IP67"),
+            Self::BeaconIndustrialRx => write!(f, "Beacon industrial-RX"),
+            Self::SuperBeacon => write!(f, "Super-Beacon"),
+            Self::SuperBeaconHedgedog => write!(f, "Super-Beacon (hedgehog mode)"),
+            Self::IndustrialSuperBeacon => write!(f, "Industrial Super-Beacon"),
+            Self::IndustrialSuperBeaconHedgedog => {
+                write!(f, "Industrial Super-Beacon (hedgehog mode)")
+            }
+            Self::SuperModem => write!(f, "Super-Modem"),
+            Self::ModemHwV51 => write!(f, "Modem HW V5.1"),
+            Self::Unknown(id) => write!(f, "Unknown device type ({})", id),
+        }
+    }
+}
+
+impl From<u8> for DeviceType {
+    fn from(id: u8) -> Self {
+        match id {
+            22 => Self::BeaconHwV45,
+            23 => Self::BeaconHwV45Hedgehog,
+            24 => Self::ModemHwV49,
+            30 => Self::BeaconHwV49,
+            31 => Self::BeaconHwV49Hedgehog,
+            32 => Self::BeaconMiniRx,
+            36 => Self::BeaconMiniTx,
+            37 => Self::BeaconTxIp67,
+            41 => Self::BeaconIndustrialRx,
+            42 => Self::SuperBeacon,
+            43 => Self::SuperBeaconHedgedog,
+            44 => Self::IndustrialSuperBeacon,
+            45 => Self::IndustrialSuperBeaconHedgedog,
+            46 => Self::SuperModem,
+            48 => Self::ModemHwV51,
+            _ => Self::Unknown(id),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod unix_millis {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::{Duration, SystemTime};
+
+    pub fn serialize<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        let millis = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_millis() as u64);
+        millis.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        let millis = u64::deserialize(deserializer)?;
+        Ok(SystemTime::UNIX_EPOCH + Duration::from_millis(millis))
+    }
+}
+
+/// Settings of Marvelmind<sup>&copy;</sup> submap
+///
+/// The bytes reserved by the api and the unused service zone point slots are kept as read,
+/// so that the settings can be written back unchanged.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SubmapSettings {
+    /// Address of the starting beacon
+    pub starting_beacon: u8,
+    /// Addresses of the starting set of beacons
+    pub starting_set: [u8; 4],
+    /// If the submap is 3D; otherwise, 2D
+    pub is_3d: bool,
+    /// If the submap is used only for Z coordinate
+    pub only_for_z: bool,
+    /// If the limitation distance is set manually
+    pub limitation_distance_is_manual: bool,
+    /// Manual limitation distance, m
+    pub max_distance_manual_m: u8,
+    /// X shift of the submap, cm
+    pub shift_x_cm: i16,
+    /// Y shift of the submap, cm
+    pub shift_y_cm: i16,
+    /// Z shift of the submap, cm
+    pub shift_z_cm: i16,
+    /// Rotation of the submap, 0.01 degree
+    pub rotation_cdeg: u16,
+    /// Plane rotation quaternion (w, x, y, z), scaled by 10000
+    pub plane_quaternion: [i16; 4],
+    /// Thickness of the service zone, cm
+    pub service_zone_thickness_cm: i16,
+    /// Height of hedgehogs in 2D mode, cm
+    pub hedges_height_2d_cm: i16,
+    /// If the submap is frozen
+    pub is_frozen: bool,
+    /// If the submap is locked
+    pub is_locked: bool,
+    /// If the beacons are higher than the hedgehogs
+    pub beacons_higher: bool,
+    /// If the submap is mirrored
+    pub is_mirrored: bool,
+    /// Addresses of the beacons in the submap, 0 means none
+    pub beacons: [u8; 4],
+    /// Ids of the nearby submaps, 255 means none
+    pub nearby_submaps: [u8; 8],
+    /// Points (x, y) of the service zone polygon, cm
+    pub service_zone_points: Vec<(i16, i16)>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    service_zone_slots: [(i16, i16); SubmapSettings::MAX_SERVICE_ZONE_POINTS],
+    reserved: [u8; 16],
+}
+
+impl SubmapSettings {
+    /// Maximum number of service zone points.
+    pub const MAX_SERVICE_ZONE_POINTS: usize = 8;
+
+    /// Get the bytes reserved by the api, as read from the modem.
+    #[inline]
+    pub fn reserved(&self) -> &[u8; 16] {
+        &self.reserved
+    }
+}
+
+/// Ultrasonic distance between two Marvelmind<sup>&copy;</sup> beacons
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Distance {
+    address_tx: u8,
+    address_rx: u8,
+    distance_mm: u32,
+    #[cfg_attr(feature = "serde", serde(with = "unix_millis"))]
+    update_time: SystemTime,
+}
+
+impl Distance {
+    /// Assemble distances into a map keyed by both `(address_tx, address_rx)` and `(address_rx, address_tx)`.
+    ///
+    /// If a pair is measured more than once, the last distance is kept.
+    pub fn symmetric_map(distances: &[Distance]) -> HashMap<(u8, u8), u32> {
+        let mut map = HashMap::<(u8, u8), u32>::new();
+
+        for distance in distances {
+            map.insert(
+                (distance.address_tx, distance.address_rx),
+                distance.distance_mm,
+            );
+            map.insert(
+                (distance.address_rx, distance.address_tx),
+                distance.distance_mm,
+            );
+        }
+
+        map
+    }
+
+    /// Get the address of the transmitting beacon.
+    #[inline]
+    pub fn address_tx(&self) -> u8 {
+        self.address_tx
+    }
+
+    /// Get the address of the receiving beacon.
+    #[inline]
+    pub fn address_rx(&self) -> u8 {
+        self.address_rx
+    }
+
+    /// Get the distance, mm.
+    #[inline]
+    pub fn distance_mm(&self) -> u32 {
+        self.distance_mm
+    }
+
+    /// Get the time information when reading the distance.
+    #[inline]
+    pub fn update_time(&self) -> SystemTime {
+        self.update_time
+    }
+}
+
+/// A single location of Marvelmind<sup>&copy;</sup> device, see [`Connection::get_device_location`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LocationFix {
+    x: i32,
+    y: i32,
+    z: i32,
+    q: u8,
+    #[cfg_attr(feature = "serde", serde(with = "unix_millis"))]
+    update_time: SystemTime,
+}
+
+impl LocationFix {
+    /// Get X coordinate, mm.
+    #[inline]
+    pub fn x(&self) -> i32 {
+        self.x
+    }
+
+    /// Get Y coordinate, mm.
+    #[inline]
+    pub fn y(&self) -> i32 {
+        self.y
+    }
+
+    /// Get Z coordinate, mm.
+    #[inline]
+    pub fn z(&self) -> i32 {
+        self.z
+    }
+
+    /// Get Quality of positioning, 0...100%.
+    #[inline]
+    pub fn q(&self) -> u8 {
+        self.q
+    }
+
+    /// Get the time when the location was read.
+    #[inline]
+    pub fn update_time(&self) -> SystemTime {
+        self.update_time
+    }
+}
+
+/// Identity of Marvelmind<sup>&copy;</sup> device, see [`Connection::get_device_version_and_id`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceIdentity {
+    firmware_version: FirmwareVersion,
+    firmware_option: u8,
+    cpu_id: [u8; 6],
+    dtype: DeviceType,
+}
+
+impl DeviceIdentity {
+    /// Get the firmware version.
+    #[inline]
+    pub fn firmware_version(&self) -> FirmwareVersion {
+        self.firmware_version
+    }
+
+    /// Get the firmware option.
+    #[inline]
+    pub fn firmware_option(&self) -> u8 {
+        self.firmware_option
+    }
+
+    /// Get the CPU ID, unique per device.
+    #[inline]
+    pub fn cpu_id(&self) -> [u8; 6] {
+        self.cpu_id
+    }
+
+    /// Get the type of the device.
+    #[inline]
+    pub fn dtype(&self) -> DeviceType {
+        self.dtype
+    }
+}
+
+fn get_last_error() -> MMError {
+    let mut err_id: U32 = U32::ZERO;
+    let res = unsafe { mm_get_last_error(&mut err_id) };
+
+    let err = match res {
+        true => match u32::from(err_id) {
+            1 => MMError::CommunicationError,
+            2 => MMError::SerialPortError,
+            3 => MMError::LicenseError,
+            0 => MMError::UnknownError,
+            code => MMError::UnknownCode(code),
+        },
+        false => MMError::UnknownError,
+    };
+
+    if !matches!(err, MMError::UnknownError) {
+        warn!("api error: {}", err);
+    }
+
+    err
+}
+
+/// Calls the api function `name`, logging its result and duration at trace level.
+#[inline]
+#[cfg_attr(
+    not(any(feature = "log", feature = "tracing")),
+    allow(unused_variables)
+)]
+fn traced<T: fmt::Debug>(name: &str, call: impl FnOnce() -> T) -> T {
+    #[cfg(any(feature = "log", feature = "tracing"))]
+    let t_start = Instant::now();
+
+    let res = call();
+    #[cfg(any(feature = "log", feature = "tracing"))]
+    trace!("{} returned {:?} in {:?}", name, res, t_start.elapsed());
+
+    res
+}
 
 /// Reads version of the API library. Required to ensure the needed functions are available in this version of library.
-pub fn api_version() -> Result<u32, MMError> {
+pub fn api_version() -> Result<ApiVersion, MMError> {
+    api_version_raw().map(ApiVersion::from)
+}
+
+/// Reads version of the API library like [`api_version`], as the packed `u32` returned by the api.
+pub fn api_version_raw() -> Result<u32, MMError> {
     let mut version: U32 = U32::ZERO;
     let res = unsafe { mm_api_version(&mut version) };
 
     match res {
-        true => Ok(version.into()),
+        true => Ok(version.into()),
+        false => Err(get_last_error()),
+    }
+}
+
+/// See [`Connection::open`].
+pub(crate) fn open_port(timeout: u64) -> Result<(), MMError> {
+    open_port_cancellable(timeout, &CancellationToken::new())
+}
+
+/// See [`Connection::open_cancellable`].
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(cancel)))]
+pub(crate) fn open_port_cancellable(
+    timeout: u64,
+    cancel: &CancellationToken,
+) -> Result<(), MMError> {
+    let t_start = Instant::now();
+    let mut attempt = 0_u64;
+    loop {
+        if cancel.is_cancelled() {
+            warn!("open port cancelled after {} attempts", attempt);
+            return Err(MMError::Cancelled);
+        }
+
+        let res = traced("mm_open_port", || unsafe { mm_open_port() });
+        attempt += 1;
+
+        if res {
+            break;
+        }
+
+        debug!("open port attempt {} failed", attempt);
+
+        if t_start.elapsed() >= Duration::from_secs(timeout) {
+            let err = get_last_error();
+            warn!(
+                "open port timed out after {} attempts in {:?}: {}",
+                attempt,
+                t_start.elapsed(),
+                err
+            );
+            return Err(err);
+        }
+
+        sleep(time::Duration::from_millis(1));
+    }
+
+    info!(
+        "port opened after {} attempts in {:?}",
+        attempt,
+        t_start.elapsed()
+    );
+
+    Ok(())
+}
+
+/// See [`Connection::close`].
+pub(crate) fn close_port() -> Result<(), MMError> {
+    let res = traced("mm_close_port", || unsafe { mm_close_port() });
+
+    match res {
+        true => Ok(()),
+        false => Err(get_last_error()),
+    }
+}
+
+/// See [`Connection::get_device_list`].
+#[cfg_attr(feature = "tracing", tracing::instrument(fields(devices)))]
+pub(crate) fn get_device_list() -> Result<DeviceList, MMError> {
+    let mut pdata = [0_u8; mem::size_of::<MMDeviceList>()];
+    let res = traced("mm_get_devices_list", || unsafe {
+        mm_get_devices_list(&mut pdata)
+    });
+
+    if !res {
+        let err = get_last_error();
+        warn!("get device list failed: {}", err);
+        return Err(err);
+    }
+
+    let device_list = MMDeviceList::ref_from_bytes(&pdata).unwrap();
+
+    let mut devices = Vec::<Device>::new();
+
+    for idx in 0..device_list.num as usize {
+        let mmdevice = &device_list.devices[idx];
+
+        let device = Device {
+            address: mmdevice.address,
+            head_index: 0,
+            is_duplicated: mmdevice.is_duplicated != 0,
+            is_sleeping: mmdevice.is_sleeping != 0,
+            v_major: mmdevice.v_major,
+            v_minor: mmdevice.v_minor,
+            v_second: mmdevice.v_second,
+            dtype: DeviceType::from(mmdevice.type_id),
+            is_connected: DeviceFlags(mmdevice.flags).is_connected(),
+            x: 0,
+            y: 0,
+            z: 0,
+            q: 0,
+            location_status: LocationStatus::default(),
+            update_time: SystemTime::UNIX_EPOCH,
+            flags: DeviceFlags(mmdevice.flags),
+        };
+
+        debug!(
+            "device discovered: address={} type={} fw={} sleeping={} connected={}",
+            device.address,
+            device.dtype,
+            device.firmware_version(),
+            device.is_sleeping,
+            device.is_connected
+        );
+
+        devices.push(device);
+    }
+
+    info!("{} devices found", devices.len());
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("devices", devices.len());
+
+    Ok(DeviceList {
+        devices,
+        last_raw_payload: None,
+        last_is_new: false,
+        index: HashMap::new(),
+        min_q: 0,
+        history: None,
+    })
+}
+
+/// See [`Connection::get_device_list_with_retry`].
+pub(crate) fn get_device_list_with_retry(timeout: Duration) -> Result<DeviceList, MMError> {
+    let t_start = Instant::now();
+    let mut attempt = 0_u64;
+    loop {
+        attempt += 1;
+
+        match get_device_list() {
+            Err(MMError::CommunicationError) if t_start.elapsed() < timeout => {
+                debug!("get device list attempt {} failed", attempt);
+                sleep(time::Duration::from_millis(1));
+            }
+            res => return res,
+        }
+    }
+}
+
+/// See [`Connection::wake_device`].
+pub(crate) fn wake_device(address: u8) -> Result<(), MMError> {
+    let res = unsafe { mm_wake_device(address) };
+
+    match res {
+        true => Ok(()),
+        false => Err(get_last_error()),
+    }
+}
+
+/// See [`Connection::sleep_device`].
+pub(crate) fn sleep_device(address: u8) -> Result<(), MMError> {
+    let res = unsafe { mm_send_to_sleep_device(address) };
+
+    match res {
+        true => Ok(()),
+        false => Err(get_last_error()),
+    }
+}
+
+/// See [`Connection::get_device_version_and_id`].
+pub(crate) fn get_device_version_and_id(address: u8) -> Result<DeviceIdentity, MMError> {
+    let mut pdata = [0_u8; mem::size_of::<MMDeviceVersion>()];
+    let res = unsafe { mm_get_device_version_and_id(address, &mut pdata) };
+
+    if !res {
+        return Err(get_last_error());
+    }
+
+    let version = MMDeviceVersion::ref_from_bytes(&pdata).unwrap();
+
+    Ok(DeviceIdentity {
+        firmware_version: FirmwareVersion {
+            major: version.v_major,
+            minor: version.v_minor,
+            second: version.v_second,
+        },
+        firmware_option: version.firmware_option,
+        cpu_id: version.cpu_id,
+        dtype: DeviceType::from(version.type_id),
+    })
+}
+
+/// See [`Connection::reset_device`].
+pub(crate) fn reset_device(address: u8) -> Result<(), MMError> {
+    let res = unsafe { mm_reset_device(address) };
+
+    match res {
+        true => Ok(()),
+        false => Err(get_last_error()),
+    }
+}
+
+/// See [`Connection::reset_modem`].
+pub(crate) fn reset_modem() -> Result<(), MMError> {
+    let devices_list = get_device_list()?;
+    let Some(modem) = devices_list.modem() else {
+        return Err(MMError::InvalidArgument("no modem found"));
+    };
+
+    reset_device(modem.address)
+}
+
+/// See [`Connection::reopen`].
+pub(crate) fn reopen_port(delay: Duration, timeout: u64) -> Result<(), MMError> {
+    let _ = close_port();
+    sleep(delay);
+
+    open_port(timeout)
+}
+
+/// See [`Connection::set_default_settings`].
+pub(crate) fn set_default_settings(address: u8) -> Result<(), MMError> {
+    let res = unsafe { mm_set_default_settings(address) };
+
+    match res {
+        true => Ok(()),
+        false => Err(get_last_error()),
+    }
+}
+
+/// Usual minimum time for a device to reboot after restoring its default settings,
+/// after which the modem no longer lists the device as connected from before the reset.
+pub const DEFAULT_SETTINGS_REBOOT_DELAY: Duration = Duration::from_secs(2);
+
+/// See [`Connection::set_default_settings_verified`].
+pub(crate) fn set_default_settings_verified(
+    address: u8,
+    reboot_delay: Duration,
+    timeout: Duration,
+) -> Result<bool, MMError> {
+    set_default_settings(address)?;
+
+    let t_start = Instant::now();
+    let mut dropped = false;
+    loop {
+        let connected = get_device_list().is_ok_and(|devices_list| {
+            devices_list
+                .connected_devices()
+                .any(|device| device.address == address)
+        });
+
+        if !connected {
+            dropped = true;
+        } else if dropped || t_start.elapsed() >= reboot_delay {
+            return Ok(true);
+        }
+
+        if t_start.elapsed() > timeout {
+            return Ok(false);
+        }
+
+        sleep(time::Duration::from_millis(100));
+    }
+}
+
+/// Maximum size of user payload, bytes, limited by the one byte payload size of the api.
+pub const MAX_PAYLOAD_SIZE: usize = u8::MAX as usize;
+
+/// See [`Connection::send_to_device`].
+pub(crate) fn send_to_device(address: u8, data: &[u8]) -> Result<(), MMError> {
+    if data.len() > MAX_PAYLOAD_SIZE {
+        return Err(MMError::PayloadTooLarge(data.len()));
+    }
+
+    let mut mmpayload = MMPayload::new_zeroed();
+    mmpayload.size_payload = data.len() as u8;
+    mmpayload.payload[..data.len()].copy_from_slice(data);
+
+    let mut pdata = [0_u8; mem::size_of::<MMPayload>()];
+    pdata.copy_from_slice(mmpayload.as_bytes());
+    let res = unsafe { mm_send_to_device(address, &pdata) };
+
+    match res {
+        true => Ok(()),
+        false => Err(get_last_error()),
+    }
+}
+
+/// Size of the header of each chunk sent by [`Connection::send_to_device_chunked`], bytes.
+pub const CHUNK_HEADER_SIZE: usize = 2;
+
+/// See [`Connection::send_to_device_chunked`].
+pub(crate) fn send_to_device_chunked(address: u8, data: &[u8]) -> Result<(), MMError> {
+    let chunk_size = MAX_PAYLOAD_SIZE - CHUNK_HEADER_SIZE;
+    let chunks_num = data.len().div_ceil(chunk_size).max(1);
+
+    if chunks_num > u8::MAX as usize {
+        return Err(MMError::PayloadTooLarge(data.len()));
+    }
+
+    let mut packet = Vec::<u8>::with_capacity(MAX_PAYLOAD_SIZE);
+    for idx in 0..chunks_num {
+        let chunk =
+            &data[(idx * chunk_size).min(data.len())..((idx + 1) * chunk_size).min(data.len())];
+
+        packet.clear();
+        packet.push(idx as u8);
+        packet.push(chunks_num as u8);
+        packet.extend_from_slice(chunk);
+
+        send_to_device(address, &packet)?;
+    }
+
+    Ok(())
+}
+
+/// See [`Connection::get_from_device`].
+pub(crate) fn get_from_device(address: u8) -> Result<Vec<u8>, MMError> {
+    let mut pdata = [0_u8; mem::size_of::<MMPayload>()];
+    let res = unsafe { mm_get_from_device(address, &mut pdata) };
+
+    if !res {
+        return Err(get_last_error());
+    }
+
+    let mmpayload = MMPayload::ref_from_bytes(&pdata).unwrap();
+
+    Ok(mmpayload.payload[..mmpayload.size_payload as usize].to_vec())
+}
+
+/// See [`Connection::set_beacon_location`].
+pub(crate) fn set_beacon_location(
+    address: u8,
+    x_mm: i32,
+    y_mm: i32,
+    z_mm: i32,
+) -> Result<(), MMError> {
+    let mmlocation = MMBeaconLocation {
+        x: x_mm.into(),
+        y: y_mm.into(),
+        z: z_mm.into(),
+    };
+
+    let mut pdata = [0_u8; mem::size_of::<MMBeaconLocation>()];
+    pdata.copy_from_slice(mmlocation.as_bytes());
+    let res = unsafe { mm_set_beacon_location(address, &pdata) };
+
+    match res {
+        true => Ok(()),
+        false => Err(get_last_error()),
+    }
+}
+
+/// See [`Connection::set_beacon_coordinate`].
+pub(crate) fn set_beacon_coordinate(address: u8, coordinate: Coordinate) -> Result<(), MMError> {
+    set_beacon_location(address, coordinate.x, coordinate.y, coordinate.z)
+}
+
+/// See [`Connection::get_beacon_height`].
+pub(crate) fn get_beacon_height(address: u8) -> Result<i32, MMError> {
+    let mut height_mm: I32 = I32::ZERO;
+    let res = unsafe { mm_get_beacon_height(address, &mut height_mm) };
+
+    match res {
+        true => Ok(height_mm.into()),
+        false => Err(get_last_error()),
+    }
+}
+
+/// See [`Connection::set_beacon_height`].
+pub(crate) fn set_beacon_height(address: u8, height_mm: i32) -> Result<(), MMError> {
+    let mut height_mm = I32::new(height_mm);
+    let res = unsafe { mm_set_beacon_height(address, &mut height_mm) };
+
+    match res {
+        true => Ok(()),
+        false => Err(get_last_error()),
+    }
+}
+
+/// See [`Connection::get_all_locations`].
+pub(crate) fn get_all_locations() -> Result<Vec<Device>, MMError> {
+    let mut pdata = [0_u8; mem::size_of::<MMLastLocations>()];
+    let update_time = SystemTime::now();
+    let res = traced("mm_get_last_locations2", || unsafe {
+        mm_get_last_locations2(&mut pdata)
+    });
+
+    if !res {
+        return Err(get_last_error());
+    }
+
+    let last_locations = MMLastLocations::ref_from_bytes(&pdata).unwrap();
+
+    if last_locations.is_new == 0 {
+        return Ok(Vec::new());
+    }
+
+    let devices = last_locations
+        .coordinates
+        .iter()
+        .filter(|coord| coord.address != 0 && coord.q <= 100)
+        .map(|coord| Device {
+            address: coord.address,
+            head_index: coord.head_index,
+            is_duplicated: false,
+            is_sleeping: false,
+            v_major: 0,
+            v_minor: 0,
+            v_second: 0,
+            dtype: DeviceType::Unknown(0),
+            is_connected: true,
+            x: coord.x.into(),
+            y: coord.y.into(),
+            z: coord.z.into(),
+            q: coord.q,
+            location_status: LocationStatus(coord.status_flag),
+            update_time,
+            flags: DeviceFlags::default(),
+        })
+        .collect();
+
+    Ok(devices)
+}
+
+/// See [`Connection::get_device_location`].
+pub(crate) fn get_device_location(address: u8) -> Result<Option<LocationFix>, MMError> {
+    let mut pdata = [0_u8; mem::size_of::<MMLastLocations>()];
+    let update_time = SystemTime::now();
+    let res = traced("mm_get_last_locations2", || unsafe {
+        mm_get_last_locations2(&mut pdata)
+    });
+
+    if !res {
+        return Err(get_last_error());
+    }
+
+    let last_locations = MMLastLocations::ref_from_bytes(&pdata).unwrap();
+
+    if last_locations.is_new == 0 {
+        return Ok(None);
+    }
+
+    let fix = last_locations
+        .coordinates
+        .iter()
+        .find(|coord| coord.address == address && coord.q <= 100)
+        .map(|coord| LocationFix {
+            x: coord.x.into(),
+            y: coord.y.into(),
+            z: coord.z.into(),
+            q: coord.q,
+            update_time,
+        });
+
+    Ok(fix)
+}
+
+/// See [`Connection::get_last_distances`].
+pub(crate) fn get_last_distances() -> Result<Vec<Distance>, MMError> {
+    let mut pdata = [0_u8; mem::size_of::<MMLastDistances>()];
+    let update_time = SystemTime::now();
+    let res = unsafe { mm_get_last_distances(&mut pdata) };
+
+    if !res {
+        return Err(get_last_error());
+    }
+
+    let last_distances = MMLastDistances::ref_from_bytes(&pdata).unwrap();
+    let num = (last_distances.num as usize).min(last_distances.distances.len());
+
+    let distances = last_distances.distances[..num]
+        .iter()
+        .filter(|mmdistance| {
+            mmdistance.address_tx != 0 && mmdistance.address_rx != 0 && mmdistance.distance != 0
+        })
+        .map(|mmdistance| Distance {
+            address_tx: mmdistance.address_tx,
+            address_rx: mmdistance.address_rx,
+            distance_mm: mmdistance.distance.into(),
+            update_time,
+        })
+        .collect();
+
+    Ok(distances)
+}
+
+/// Confirmation required by [`Connection::erase_map`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EraseConfirm {
+    /// Yes, erase the map
+    Yes,
+}
+
+/// See [`Connection::erase_map`].
+pub(crate) fn erase_map(confirm: EraseConfirm) -> Result<(), MMError> {
+    let EraseConfirm::Yes = confirm;
+    let res = unsafe { mm_erase_map() };
+
+    match res {
+        true => Ok(()),
         false => Err(get_last_error()),
     }
 }
 
-/// Opens port where Marvelmind<sup>&copy;</sup> device (modem or beacon) is connected via USB (virtual serial port).
-/// You don’t need to specify serial port name, because the API searching all serial ports and checks whether it corresponds to Marvelmind device or no.
-///
-/// # Arguments
-/// * `timeout` - Maximum wait time in seconds before aborting.
-///   Note: A value of 0 will attempt exactly one opening attempt.
-pub fn open_port(timeout: u64) -> Result<(), MMError> {
+/// See [`Connection::add_submap`].
+pub(crate) fn add_submap(submap_id: u8) -> Result<(), MMError> {
+    let res = unsafe { mm_add_submap(submap_id) };
+
+    match res {
+        true => Ok(()),
+        false => Err(get_last_error()),
+    }
+}
+
+/// Maximum submap id, 255 is used by the api as no submap.
+pub const MAX_SUBMAP_ID: u8 = 254;
+
+/// See [`Connection::delete_submap`].
+pub(crate) fn delete_submap(submap_id: u8) -> Result<(), MMError> {
+    if get_submap_settings(submap_id)?.is_frozen {
+        return Err(MMError::SubmapFrozen(submap_id));
+    }
+
+    let res = unsafe { mm_delete_submap(submap_id) };
+
+    match res {
+        true => Ok(()),
+        false => Err(get_last_error()),
+    }
+}
+
+/// See [`Connection::delete_all_submaps`].
+pub(crate) fn delete_all_submaps() -> HashMap<u8, Result<(), MMError>> {
+    delete_all_submaps_cancellable(&CancellationToken::new())
+}
+
+/// See [`Connection::delete_all_submaps_cancellable`].
+pub(crate) fn delete_all_submaps_cancellable(
+    cancel: &CancellationToken,
+) -> HashMap<u8, Result<(), MMError>> {
+    let mut results = HashMap::<u8, Result<(), MMError>>::new();
+
+    for submap_id in 0..=MAX_SUBMAP_ID {
+        if cancel.is_cancelled() {
+            break;
+        }
+
+        if get_submap_settings(submap_id).is_ok() {
+            results.insert(submap_id, delete_submap(submap_id));
+        }
+    }
+
+    results
+}
+
+/// See [`Connection::freeze_submap`].
+pub(crate) fn freeze_submap(submap_id: u8) -> Result<(), MMError> {
+    let res = unsafe { mm_freeze_submap(submap_id) };
+
+    match res {
+        true => Ok(()),
+        false => Err(get_last_error()),
+    }
+}
+
+/// See [`Connection::unfreeze_submap`].
+pub(crate) fn unfreeze_submap(submap_id: u8) -> Result<(), MMError> {
+    let res = unsafe { mm_unfreeze_submap(submap_id) };
+
+    match res {
+        true => Ok(()),
+        false => Err(get_last_error()),
+    }
+}
+
+/// See [`Connection::get_submap_settings`].
+pub(crate) fn get_submap_settings(submap_id: u8) -> Result<SubmapSettings, MMError> {
+    let mut pdata = [0_u8; mem::size_of::<MMSubmapSettings>()];
+    let res = unsafe { mm_get_submap_settings(submap_id, &mut pdata) };
+
+    if !res {
+        return Err(get_last_error());
+    }
+
+    let settings = MMSubmapSettings::ref_from_bytes(&pdata).unwrap();
+    let points_num =
+        (settings.service_zone_points_num as usize).min(settings.service_zone_points.len());
+
+    Ok(SubmapSettings {
+        starting_beacon: settings.starting_beacon,
+        starting_set: settings.starting_set,
+        is_3d: settings.enabled_3d != 0,
+        only_for_z: settings.only_for_z != 0,
+        limitation_distance_is_manual: settings.limitation_distance_is_manual != 0,
+        max_distance_manual_m: settings.max_distance_manual_m,
+        shift_x_cm: settings.shift_x_cm.into(),
+        shift_y_cm: settings.shift_y_cm.into(),
+        shift_z_cm: settings.shift_z_cm.into(),
+        rotation_cdeg: settings.rotation_cdeg.into(),
+        plane_quaternion: settings.plane_quaternion.map(i16::from),
+        service_zone_thickness_cm: settings.service_zone_thickness_cm.into(),
+        hedges_height_2d_cm: settings.hedges_height_2d_cm.into(),
+        is_frozen: settings.frozen != 0,
+        is_locked: settings.locked != 0,
+        beacons_higher: settings.beacons_higher != 0,
+        is_mirrored: settings.mirrored != 0,
+        beacons: settings.beacons,
+        nearby_submaps: settings.nearby_submaps,
+        service_zone_points: settings.service_zone_points[..points_num]
+            .iter()
+            .map(|point| (point.x.into(), point.y.into()))
+            .collect(),
+        service_zone_slots: settings
+            .service_zone_points
+            .each_ref()
+            .map(|point| (point.x.into(), point.y.into())),
+        reserved: settings.reserved,
+    })
+}
+
+/// See [`Connection::set_submap_settings`].
+pub(crate) fn set_submap_settings(submap_id: u8, settings: &SubmapSettings) -> Result<(), MMError> {
+    if settings.service_zone_points.len() > SubmapSettings::MAX_SERVICE_ZONE_POINTS {
+        return Err(MMError::InvalidArgument("too many service zone points"));
+    }
+
+    if settings.rotation_cdeg >= 36000 {
+        return Err(MMError::InvalidArgument("submap rotation out of range"));
+    }
+
+    let is_submap_beacon = |address: u8| address == 0 || settings.beacons.contains(&address);
+
+    if !is_submap_beacon(settings.starting_beacon) {
+        return Err(MMError::InvalidArgument(
+            "starting beacon is not a beacon of the submap",
+        ));
+    }
+
+    if !settings
+        .starting_set
+        .iter()
+        .all(|&address| is_submap_beacon(address))
+    {
+        return Err(MMError::InvalidArgument(
+            "starting set beacon is not a beacon of the submap",
+        ));
+    }
+
+    let mut service_zone_points = <[MMPoint; 8]>::new_zeroed();
+    for (idx, point) in service_zone_points.iter_mut().enumerate() {
+        let (x, y) = settings
+            .service_zone_points
+            .get(idx)
+            .copied()
+            .unwrap_or(settings.service_zone_slots[idx]);
+        point.x = x.into();
+        point.y = y.into();
+    }
+
+    let mmsettings = MMSubmapSettings {
+        starting_beacon: settings.starting_beacon,
+        starting_set: settings.starting_set,
+        enabled_3d: settings.is_3d.into(),
+        only_for_z: settings.only_for_z.into(),
+        limitation_distance_is_manual: settings.limitation_distance_is_manual.into(),
+        max_distance_manual_m: settings.max_distance_manual_m,
+        shift_x_cm: settings.shift_x_cm.into(),
+        shift_y_cm: settings.shift_y_cm.into(),
+        shift_z_cm: settings.shift_z_cm.into(),
+        rotation_cdeg: settings.rotation_cdeg.into(),
+        plane_quaternion: settings.plane_quaternion.map(I16::from),
+        service_zone_thickness_cm: settings.service_zone_thickness_cm.into(),
+        hedges_height_2d_cm: settings.hedges_height_2d_cm.into(),
+        frozen: settings.is_frozen.into(),
+        locked: settings.is_locked.into(),
+        beacons_higher: settings.beacons_higher.into(),
+        mirrored: settings.is_mirrored.into(),
+        beacons: settings.beacons,
+        nearby_submaps: settings.nearby_submaps,
+        service_zone_points_num: settings.service_zone_points.len() as u8,
+        service_zone_points,
+        reserved: settings.reserved,
+    };
+
+    let mut pdata = [0_u8; mem::size_of::<MMSubmapSettings>()];
+    pdata.copy_from_slice(mmsettings.as_bytes());
+    let res = unsafe { mm_set_submap_settings(submap_id, &pdata) };
+
+    match res {
+        true => Ok(()),
+        false => Err(get_last_error()),
+    }
+}
+
+/// Minimum supported location update rate, Hz.
+pub const MIN_UPDATE_RATE: f32 = 0.05;
+/// Maximum supported location update rate, Hz.
+pub const MAX_UPDATE_RATE: f32 = 16.0;
+
+/// See [`Connection::get_update_rate`].
+pub(crate) fn get_update_rate() -> Result<f32, MMError> {
+    let mut rate_mhz: U32 = U32::ZERO;
+    let res = unsafe { mm_get_update_rate_setting(&mut rate_mhz) };
+
+    match res {
+        true => Ok(u32::from(rate_mhz) as f32 / 1000.0),
+        false => Err(get_last_error()),
+    }
+}
+
+/// See [`Connection::set_update_rate`].
+pub(crate) fn set_update_rate(hz: f32) -> Result<(), MMError> {
+    if !(MIN_UPDATE_RATE..=MAX_UPDATE_RATE).contains(&hz) {
+        return Err(MMError::InvalidArgument("update rate out of range"));
+    }
+
+    let mut rate_mhz = U32::new((hz * 1000.0).round() as u32);
+    let res = unsafe { mm_set_update_rate_setting(&mut rate_mhz) };
+
+    match res {
+        true => Ok(()),
+        false => Err(get_last_error()),
+    }
+}
+
+/// See [`Connection::set_update_rate_verified`].
+pub(crate) fn set_update_rate_verified(hz: f32, timeout: Duration) -> Result<bool, MMError> {
+    set_update_rate(hz)?;
+
+    let expected_mhz = (hz * 1000.0).round();
     let t_start = Instant::now();
     loop {
-        if t_start.elapsed().as_secs() > timeout {
-            return Err(get_last_error());
+        if (get_update_rate()? * 1000.0).round() == expected_mhz {
+            return Ok(true);
         }
 
-        let res = unsafe { mm_open_port() };
-
-        match res {
-            true => break,
-            false => match res {
-                true => break,
-                false => sleep(time::Duration::from_millis(1)),
-            },
+        if t_start.elapsed() > timeout {
+            return Ok(false);
         }
+
+        sleep(time::Duration::from_millis(10));
     }
+}
 
-    Ok(())
+/// Minimum supported air temperature, °C.
+pub const MIN_AIR_TEMPERATURE: i8 = -40;
+
+/// Maximum supported air temperature, °C.
+pub const MAX_AIR_TEMPERATURE: i8 = 80;
+
+/// See [`Connection::get_air_temperature`].
+pub(crate) fn get_air_temperature() -> Result<i8, MMError> {
+    let mut celsius = 0_i8;
+    let res = unsafe { mm_get_air_temperature(&mut celsius) };
+
+    match res {
+        true => Ok(celsius),
+        false => Err(get_last_error()),
+    }
 }
 
-/// Closes port, if it was previously opened by `open_port` function.
-pub fn close_port() -> Result<(), MMError> {
-    let res = unsafe { mm_close_port() };
+/// See [`Connection::set_air_temperature`].
+pub(crate) fn set_air_temperature(celsius: i8) -> Result<(), MMError> {
+    if !(MIN_AIR_TEMPERATURE..=MAX_AIR_TEMPERATURE).contains(&celsius) {
+        return Err(MMError::InvalidArgument("air temperature out of range"));
+    }
+
+    let mut celsius = celsius;
+    let res = unsafe { mm_set_air_temperature(&mut celsius) };
 
     match res {
         true => Ok(()),
@@ -395,43 +2969,629 @@ pub fn close_port() -> Result<(), MMError> {
     }
 }
 
-/// Reads list of Marvelmind<sup>&copy;</sup> devices known to modem.
-/// The list includes list of all devices connected by radio to modem’s network, including sleeping devices.
-pub fn get_device_list() -> Result<DeviceList, MMError> {
-    let mut pdata = [0 as u8; mem::size_of::<MMDeviceList>()];
-    let res = unsafe { mm_get_devices_list(&mut pdata) };
+/// Spawns a thread reading an external temperature `sensor` every `interval`, °C,
+/// which writes the air temperature setting whenever the rounded temperature changes.
+///
+/// The temperature is clamped to `MIN_AIR_TEMPERATURE..=MAX_AIR_TEMPERATURE`, NaN readings are skipped.
+/// Failed writes are retried at the next reading. The thread stops once `cancel` is cancelled
+/// and gives `connection` back.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use marvelmind::{self as mm, CancellationToken, Connection};
+/// use std::time::Duration;
+///
+/// let connection = Connection::open(30).unwrap();
+///
+/// let cancel = CancellationToken::new();
+/// let handle = mm::spawn_air_temperature_updater(
+///     connection,
+///     || -12.4, // read the external sensor here
+///     Duration::from_secs(60),
+///     cancel.clone(),
+/// );
+///
+/// // ...
+///
+/// cancel.cancel();
+/// let connection = handle.join().unwrap();
+/// ```
+pub fn spawn_air_temperature_updater<F>(
+    connection: Connection,
+    sensor: F,
+    interval: Duration,
+    cancel: CancellationToken,
+) -> JoinHandle<Connection>
+where
+    F: Fn() -> f32 + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut last_written = None::<i8>;
+
+        while !cancel.is_cancelled() {
+            let reading = sensor();
+
+            if !reading.is_nan() {
+                let celsius = reading
+                    .round()
+                    .clamp(MIN_AIR_TEMPERATURE as f32, MAX_AIR_TEMPERATURE as f32)
+                    as i8;
+
+                if last_written != Some(celsius) {
+                    match connection.set_air_temperature(celsius) {
+                        Ok(()) => last_written = Some(celsius),
+                        Err(err) => {
+                            warn!("failed to set air temperature: {}", err);
+                        }
+                    }
+                }
+            }
+
+            sleep(interval);
+        }
+
+        connection
+    })
+}
+
+/// Spawns a thread polling the last locations every `interval`,
+/// which sends a copy of the devices list over the channel whenever a location is updated.
+///
+/// The thread reads the devices list with `get_device_list` once at start and stops if that fails.
+/// Failed location reads are skipped. The thread stops at the next update after the receiver is dropped,
+/// and gives `connection` back.
+pub fn spawn_poller(
+    connection: Connection,
+    interval: Duration,
+) -> (JoinHandle<Connection>, Receiver<DeviceList>) {
+    let (tx, rx) = mpsc::channel();
+
+    let handle = thread::spawn(move || {
+        let Ok(mut device_list) = connection.get_device_list() else {
+            return connection;
+        };
+
+        let interval_ms = AtomicU64::new(interval.as_millis() as u64);
+        let is_stopped = AtomicBool::new(false);
+        poller::poll_loop(
+            &mut device_list,
+            &interval_ms,
+            &is_stopped,
+            |device_list, res| poller::send_device_list(&tx, device_list, res),
+        );
 
-    if res == false {
-        return Err(get_last_error());
+        connection
+    });
+
+    (handle, rx)
+}
+
+/// Streams a copy of the devices list whenever a location is updated, polling the last locations every `interval`.
+///
+/// The devices list is read with `get_device_list` once at start, the stream ends if that fails.
+/// The blocking api calls run on `spawn_blocking` threads, so this must be called within a tokio runtime.
+/// The stream takes `connection`, the port is closed when the stream ends.
+#[cfg(feature = "tokio")]
+pub fn location_stream(
+    connection: Connection,
+    interval: Duration,
+) -> impl tokio_stream::Stream<Item = DeviceList> {
+    let (tx, rx) = tokio::sync::mpsc::channel(1);
+
+    tokio::spawn(async move {
+        let Ok((mut connection, Ok(mut device_list))) = tokio::task::spawn_blocking(move || {
+            let res = connection.get_device_list();
+            (connection, res)
+        })
+        .await
+        else {
+            return;
+        };
+
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+
+            let Ok((conn, list, res)) = tokio::task::spawn_blocking(move || {
+                let res = device_list.update_last_locations(&connection);
+                (connection, device_list, res)
+            })
+            .await
+            else {
+                return;
+            };
+            connection = conn;
+            device_list = list;
+
+            if let Ok(updated) = res {
+                if !updated.is_empty() && tx.send(device_list.clone()).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    tokio_stream::wrappers::ReceiverStream::new(rx)
+}
+
+/// See [`Connection::open_async`].
+#[cfg(feature = "tokio")]
+pub(crate) async fn open_port_async(timeout: Duration) -> Result<(), MMError> {
+    let t_start = Instant::now();
+    loop {
+        // The last error is read on the blocking thread as well, the
+        // dashapi call must not run on the async task.
+        let res = tokio::task::spawn_blocking(move || {
+            if traced("mm_open_port", || unsafe { mm_open_port() }) {
+                Some(Ok(()))
+            } else if t_start.elapsed() >= timeout {
+                Some(Err(get_last_error()))
+            } else {
+                None
+            }
+        })
+        .await
+        .unwrap_or(Some(Err(MMError::UnknownError)));
+
+        match res {
+            Some(Ok(())) => break,
+            Some(Err(e)) => return Err(e),
+            None => {}
+        }
+
+        tokio::time::sleep(time::Duration::from_millis(1)).await;
     }
 
-    let device_list = MMDeviceList::ref_from_bytes(&pdata).unwrap();
+    Ok(())
+}
 
-    let mut devices = Vec::<Device>::new();
-    let update_time = SystemTime::now();
+/// See [`Connection::close_async`].
+#[cfg(feature = "tokio")]
+pub(crate) async fn close_port_async() -> Result<(), MMError> {
+    tokio::task::spawn_blocking(close_port)
+        .await
+        .unwrap_or(Err(MMError::UnknownError))
+}
 
-    for idx in 0..device_list.num as usize {
-        let mmdevice = &device_list.devices[idx];
+/// See [`Connection::get_device_list_async`].
+#[cfg(feature = "tokio")]
+pub(crate) async fn get_device_list_async() -> Result<DeviceList, MMError> {
+    tokio::task::spawn_blocking(get_device_list)
+        .await
+        .unwrap_or(Err(MMError::UnknownError))
+}
 
-        let device = Device {
-            address: mmdevice.address,
-            is_duplicated: mmdevice.is_duplicated != 0,
-            is_sleeping: mmdevice.is_sleeping != 0,
-            v_major: mmdevice.v_major,
-            v_minor: mmdevice.v_minor,
-            v_second: mmdevice.v_second,
-            dtype: DeviceType::try_from(mmdevice.type_id)
-                .unwrap_or_else(|_| panic!("unsupported device type id: {}", mmdevice.type_id)),
-            is_connected: mmdevice.flags & 0b00000001 > 0,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(address: u8, dtype: DeviceType) -> Device {
+        Device {
+            address,
+            head_index: 0,
+            is_duplicated: false,
+            is_sleeping: false,
+            v_major: 7,
+            v_minor: 0,
+            v_second: 0,
+            dtype,
+            is_connected: true,
             x: 0,
             y: 0,
             z: 0,
             q: 0,
-            update_time: update_time,
+            location_status: LocationStatus::default(),
+            update_time: SystemTime::UNIX_EPOCH,
+            flags: DeviceFlags(1),
+        }
+    }
+
+    /// Bytes of a coordinate of the last locations packet.
+    fn coordinate_bytes(address: u8, x: i32, y: i32, z: i32, status: u8, q: u8) -> Vec<u8> {
+        let mut bytes = vec![address, 0];
+        bytes.extend_from_slice(&x.to_le_bytes());
+        bytes.extend_from_slice(&y.to_le_bytes());
+        bytes.extend_from_slice(&z.to_le_bytes());
+        bytes.extend_from_slice(&[status, q, 0, 0, 0, 0]);
+        bytes
+    }
+
+    /// Bytes of a last locations packet without payload.
+    fn last_locations_bytes(is_new: bool, coordinates: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = coordinates.concat();
+        bytes.resize(6 * mem::size_of::<MMCoordinate>(), 0);
+        bytes.push(is_new.into());
+        bytes.resize(mem::size_of::<MMLastLocations>(), 0);
+        bytes
+    }
+
+    #[test]
+    fn fresh_locations_are_applied_with_their_status() {
+        let mut device_list = DeviceList::from_devices(
+            [
+                device(10, DeviceType::SuperBeaconHedgedog),
+                device(11, DeviceType::SuperBeaconHedgedog),
+            ],
+            false,
+        )
+        .unwrap();
+
+        let bytes = last_locations_bytes(
+            true,
+            &[
+                coordinate_bytes(10, 1000, -2000, 300, 0b0000_0000, 90),
+                coordinate_bytes(11, 0, 0, 0, 0b1000_0001, 0),
+            ],
+        );
+        let last_locations = MMLastLocations::ref_from_bytes(&bytes).unwrap();
+        let updated =
+            device_list.apply_locations(last_locations, SystemTime::now(), &HashMap::new(), 0, &[]);
+
+        assert_eq!(updated, vec![(10, 0), (11, 0)]);
+
+        let fresh = device_list.get(10).unwrap();
+        assert_eq!(
+            (fresh.x(), fresh.y(), fresh.z(), fresh.q()),
+            (1000, -2000, 300, 90)
+        );
+        assert!(!fresh.location_status().is_unavailable());
+
+        let unavailable = device_list.get(11).unwrap();
+        assert!(unavailable.location_status().is_unavailable());
+        assert_eq!(unavailable.location_status().raw(), 0b1000_0001);
+    }
+
+    #[test]
+    fn stale_locations_are_not_applied() {
+        let mut device_list =
+            DeviceList::from_devices([device(10, DeviceType::SuperBeaconHedgedog)], false).unwrap();
+
+        let fresh = last_locations_bytes(true, &[coordinate_bytes(10, 1000, 0, 0, 0, 90)]);
+        let last_locations = MMLastLocations::ref_from_bytes(&fresh).unwrap();
+        device_list.apply_locations(last_locations, SystemTime::now(), &HashMap::new(), 0, &[]);
+        let update_time = device_list.get(10).unwrap().update_time();
+
+        // the modem repeats the same coordinates, not new
+        let stale = last_locations_bytes(false, &[coordinate_bytes(10, 5000, 0, 0, 1, 10)]);
+        let last_locations = MMLastLocations::ref_from_bytes(&stale).unwrap();
+        let updated =
+            device_list.apply_locations(last_locations, SystemTime::now(), &HashMap::new(), 0, &[]);
+
+        assert!(updated.is_empty());
+        assert!(!device_list.last_is_new());
+
+        let device = device_list.get(10).unwrap();
+        assert_eq!((device.x(), device.q()), (1000, 90));
+        assert!(!device.location_status().is_unavailable());
+        assert_eq!(device.update_time(), update_time);
+    }
+
+    const DEVICE_TYPES: [DeviceType; 15] = [
+        DeviceType::BeaconHwV45,
+        DeviceType::BeaconHwV45Hedgehog,
+        DeviceType::ModemHwV49,
+        DeviceType::BeaconHwV49,
+        DeviceType::BeaconHwV49Hedgehog,
+        DeviceType::BeaconMiniRx,
+        DeviceType::BeaconMiniTx,
+        DeviceType::BeaconTxIp67,
+        DeviceType::BeaconIndustrialRx,
+        DeviceType::SuperBeacon,
+        DeviceType::SuperBeaconHedgedog,
+        DeviceType::IndustrialSuperBeacon,
+        DeviceType::IndustrialSuperBeaconHedgedog,
+        DeviceType::SuperModem,
+        DeviceType::ModemHwV51,
+    ];
+
+    #[test]
+    fn device_type_ids_round_trip() {
+        for dtype in DEVICE_TYPES {
+            assert_eq!(DeviceType::from(dtype.type_id()), dtype);
+        }
+
+        for id in 0..=u8::MAX {
+            let dtype = DeviceType::from(id);
+            assert_eq!(dtype.type_id(), id);
+            assert_eq!(
+                matches!(dtype, DeviceType::Unknown(_)),
+                !DEVICE_TYPES.contains(&dtype),
+                "{}",
+                id
+            );
+        }
+
+        for id in [0, 99, 255] {
+            assert_eq!(DeviceType::from(id), DeviceType::Unknown(id));
+        }
+    }
+
+    #[test]
+    fn device_type_names_are_unique() {
+        let names: Vec<String> = DEVICE_TYPES.iter().map(ToString::to_string).collect();
+
+        for (i, name) in names.iter().enumerate() {
+            assert!(!name.is_empty());
+            assert!(!names[..i].contains(name), "{}", name);
+        }
+
+        assert_eq!(
+            DeviceType::Unknown(99).to_string(),
+            "Unknown device type (99)"
+        );
+    }
+
+    #[test]
+    fn device_type_capabilities() {
+        use DeviceType::*;
+
+        // type, generation, 3D, IMU, beacon, modem, hedgehog
+        let table = [
+            (BeaconHwV45, 4, true, false, true, false, false),
+            (BeaconHwV45Hedgehog, 4, true, false, false, false, true),
+            (ModemHwV49, 4, false, false, false, true, false),
+            (BeaconHwV49, 4, true, true, true, false, false),
+            (BeaconHwV49Hedgehog, 4, true, true, false, false, true),
+            (BeaconMiniRx, 0, true, false, true, false, false),
+            (BeaconMiniTx, 0, true, false, true, false, false),
+            (BeaconTxIp67, 0, true, false, true, false, false),
+            (BeaconIndustrialRx, 0, true, false, true, false, false),
+            (SuperBeacon, 0, true, true, true, false, false),
+            (SuperBeaconHedgedog, 0, true, true, false, false, true),
+            (IndustrialSuperBeacon, 0, true, true, true, false, false),
+            (
+                IndustrialSuperBeaconHedgedog,
+                0,
+                true,
+                true,
+                false,
+                false,
+                true,
+            ),
+            (SuperModem, 0, false, false, false, true, false),
+            (ModemHwV51, 5, false, false, false, true, false),
+        ];
+
+        assert_eq!(
+            table.map(|(dtype, ..)| dtype),
+            DEVICE_TYPES,
+            "every type in order"
+        );
+
+        for (dtype, generation, is_3d, imu, is_beacon, is_modem, is_hedgehog) in table {
+            assert_eq!(dtype.hardware_generation(), generation, "{:?}", dtype);
+            assert_eq!(dtype.supports_3d(), is_3d, "{:?}", dtype);
+            assert_eq!(dtype.supports_imu(), imu, "{:?}", dtype);
+            assert_eq!(dtype.is_beacon(), is_beacon, "{:?}", dtype);
+            assert_eq!(dtype.is_modem(), is_modem, "{:?}", dtype);
+            assert_eq!(dtype.is_hedgehog(), is_hedgehog, "{:?}", dtype);
+            // a device is exactly one of beacon, modem or hedgehog
+            assert_eq!(
+                [is_beacon, is_modem, is_hedgehog]
+                    .iter()
+                    .filter(|&&is| is)
+                    .count(),
+                1,
+                "{:?}",
+                dtype
+            );
+        }
+
+        let unknown = DeviceType::Unknown(99);
+        assert_eq!(unknown.hardware_generation(), 0);
+        assert!(!unknown.supports_3d());
+        assert!(!unknown.supports_imu());
+        assert!(!unknown.is_beacon() && !unknown.is_modem() && !unknown.is_hedgehog());
+    }
+
+    #[test]
+    fn firmware_versions_are_formatted() {
+        // (major, minor, second), canonical, dashboard
+        let table = [
+            ((6, 7, 1), "6.07.1", "V6.07a"),
+            ((6, 7, 0), "6.07.0", "V6.07"),
+            ((7, 240, 26), "7.240.26", "V7.240z"),
+            ((7, 0, 27), "7.00.27", "V7.00.27"),
+        ];
+
+        for ((major, minor, second), canonical, dashboard) in table {
+            let version = FirmwareVersion {
+                major,
+                minor,
+                second,
+            };
+            assert_eq!(version.to_string(), canonical);
+            assert_eq!(format!("{:#}", version), dashboard);
+        }
+    }
+
+    #[test]
+    fn device_flags_are_decoded() {
+        // raw, connected
+        let table = [
+            (0b0000_0000, false),
+            (0b0000_0001, true),
+            (0b0000_0010, false),
+            (0b0000_1110, false),
+            (0b1111_0001, true),
+            (0b1111_1111, true),
+        ];
+
+        for (raw, connected) in table {
+            let flags = DeviceFlags::from(raw);
+            assert_eq!(flags.raw(), raw, "raw {:#010b}", raw);
+            assert_eq!(flags.is_connected(), connected, "raw {:#010b}", raw);
+        }
+    }
+
+    #[test]
+    fn freshness_follows_the_update_time() {
+        let mut device = device(10, DeviceType::SuperBeaconHedgedog);
+        assert_eq!(device.age(), None, "never located");
+        assert!(!device.is_fresh(Duration::MAX));
+
+        device.update_time = SystemTime::now() - Duration::from_secs(2);
+        assert!(device.age().unwrap() >= Duration::from_secs(2));
+        assert!(device.is_fresh(Duration::from_secs(60)));
+        assert!(!device.is_fresh(Duration::from_secs(1)));
+
+        // a clock step back makes the location look new rather than failing
+        device.update_time = SystemTime::now() + Duration::from_secs(60);
+        assert_eq!(device.age(), Some(Duration::ZERO));
+        assert!(device.is_fresh(Duration::from_millis(1)));
+    }
+
+    #[test]
+    fn duplicated_addresses_keep_the_first_device() {
+        let head = |head_index, x| Device {
+            head_index,
+            x,
+            ..device(10, DeviceType::IndustrialSuperBeacon)
         };
 
-        devices.push(device);
+        let res = DeviceList::from_devices([head(0, 1000), head(0, 2000)], false);
+        assert!(matches!(res, Err(MMError::DuplicateAddress(10))));
+
+        let mut device_list =
+            DeviceList::from_devices([head(0, 1000), head(0, 2000), head(1, 3000)], true).unwrap();
+        let duplicated: Vec<bool> = device_list
+            .devices()
+            .iter()
+            .map(Device::is_duplicated)
+            .collect();
+        assert_eq!(duplicated, [true, true, false]);
+
+        // the heads of a multi-head beacon are not duplicates
+        let heads = DeviceList::from_devices([head(0, 1000), head(1, 3000)], false).unwrap();
+        assert_eq!(heads.devices().len(), 2);
+
+        assert_eq!(device_list.get(10).unwrap().x(), 1000);
+        assert_eq!(device_list.by_address_map()[&10].x(), 1000);
+        assert_eq!(device_list.to_map()[&10].x(), 1000);
+
+        device_list.build_index();
+        assert_eq!(device_list.get(10).unwrap().x(), 1000);
+    }
+
+    fn coordinate(x: i32, y: i32, z: i32) -> Coordinate {
+        Coordinate { x, y, z }
+    }
+
+    /// Connected, awake hedgehog located at the given coordinate with 100% quality.
+    fn located(address: u8, x: i32, y: i32, z: i32) -> Device {
+        Device {
+            x,
+            y,
+            z,
+            q: 100,
+            ..device(address, DeviceType::SuperBeaconHedgedog)
+        }
+    }
+
+    #[test]
+    fn centroid_and_bounding_box() {
+        let empty = DeviceList::from_devices([], false).unwrap();
+        assert_eq!(empty.centroid(), None);
+        assert_eq!(empty.bounding_box(), None);
+
+        let single = DeviceList::from_devices([located(10, 1000, -2000, 300)], false).unwrap();
+        let point = coordinate(1000, -2000, 300);
+        assert_eq!(single.centroid(), Some(point));
+        assert_eq!(single.bounding_box(), Some((point, point)));
+
+        let device_list = DeviceList::from_devices(
+            [
+                located(10, 0, 0, 0),
+                located(11, 3000, -1000, 600),
+                located(12, 0, 4000, 0),
+                // not located: zero quality, sleeping or disconnected
+                Device {
+                    q: 0,
+                    ..located(13, 90000, 90000, 90000)
+                },
+                Device {
+                    is_sleeping: true,
+                    ..located(14, 90000, 90000, 90000)
+                },
+                Device {
+                    is_connected: false,
+                    ..located(15, 90000, 90000, 90000)
+                },
+            ],
+            false,
+        )
+        .unwrap();
+        assert_eq!(device_list.centroid(), Some(coordinate(1000, 1000, 200)));
+        assert_eq!(
+            device_list.bounding_box(),
+            Some((coordinate(0, -1000, 0), coordinate(3000, 4000, 600)))
+        );
     }
 
-    Ok(DeviceList { devices: devices })
+    #[test]
+    fn nearest_to_prefers_the_lower_address() {
+        let empty = DeviceList::from_devices([], false).unwrap();
+        assert_eq!(empty.nearest_to(&coordinate(0, 0, 0)), None);
+
+        let device_list = DeviceList::from_devices(
+            [
+                located(12, 1000, 0, 0),
+                located(11, -1000, 0, 0),
+                located(13, 0, 1000, 0),
+                // nearer, but not located
+                Device {
+                    q: 0,
+                    ..located(10, 0, 0, 0)
+                },
+            ],
+            false,
+        )
+        .unwrap();
+
+        let nearest = |x, y| {
+            device_list
+                .nearest_to(&coordinate(x, y, 0))
+                .map(Device::address)
+        };
+        assert_eq!(nearest(0, 0), Some(11), "three at the same distance");
+        assert_eq!(nearest(900, 0), Some(12));
+        assert_eq!(nearest(0, 900), Some(13));
+    }
+
+    #[test]
+    fn devices_are_filtered_by_kind_and_state() {
+        let device_list = DeviceList::from_devices(
+            [
+                device(1, DeviceType::ModemHwV51),
+                device(2, DeviceType::BeaconHwV49),
+                Device {
+                    is_sleeping: true,
+                    ..device(3, DeviceType::SuperBeacon)
+                },
+                device(10, DeviceType::SuperBeaconHedgedog),
+                Device {
+                    is_sleeping: true,
+                    is_connected: false,
+                    ..device(11, DeviceType::BeaconHwV49Hedgehog)
+                },
+                device(20, DeviceType::Unknown(99)),
+            ],
+            false,
+        )
+        .unwrap();
+
+        let addresses = |devices: &mut dyn Iterator<Item = &Device>| {
+            devices.map(Device::address).collect::<Vec<_>>()
+        };
+        assert_eq!(addresses(&mut device_list.beacons()), [2, 3]);
+        assert_eq!(addresses(&mut device_list.hedgehogs()), [10, 11]);
+        assert_eq!(addresses(&mut device_list.modems()), [1]);
+        assert_eq!(addresses(&mut device_list.sleeping_devices()), [3, 11]);
+        assert_eq!(
+            addresses(&mut device_list.connected_devices()),
+            [1, 2, 3, 10, 20]
+        );
+        assert_eq!(device_list.modem().map(Device::address), Some(1));
+    }
 }
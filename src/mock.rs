@@ -0,0 +1,1107 @@
+// Copyright 2025 wyzdwdz <wyzdwdz@gmail.com>
+//
+// Licensed under the MIT license <LICENSE or https://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to
+// those terms.
+
+//! In-memory stand-in for the Marvelmind<sup>&copy;</sup> api library
+//!
+//! With the `mock` feature, the api functions are Rust stubs reading an injectable device table
+//! instead of the `dashapi` library, so the crate can be tested without hardware.
+//!
+//! Port, devices list, last locations, user payload, update rate, air temperature, beacon height,
+//! submap settings and default settings calls are simulated. The settings of a submap are only readable
+//! once written. Other api calls fail with `MMError::UnknownError`.
+//!
+//! The stubs share one global state, [`MockDeviceLayer`] is an independent [`DeviceLayer`]
+//! for tests running in parallel.
+//!
+//! # Example
+//!
+//! ```rust
+//! use marvelmind::{mock::{self, MockDevice, MockLocation}, Connection, DeviceType};
+//!
+//! mock::set_devices(&[MockDevice::new(10, DeviceType::SuperBeaconHedgedog)]);
+//! mock::set_locations(&[MockLocation::new(10, 1000, 2000, 0, 100)]);
+//!
+//! let connection = Connection::open(0).unwrap();
+//!
+//! let mut devices_list = connection.get_device_list().unwrap();
+//! assert_eq!(devices_list.update_last_locations(&connection).unwrap(), vec![10]);
+//! assert_eq!(devices_list.get(10).unwrap().x(), 1000);
+//!
+//! // the locations are new only once
+//! assert!(devices_list.update_last_locations(&connection).unwrap().is_empty());
+//! ```
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Mutex, MutexGuard},
+    time::SystemTime,
+};
+
+use crate::{Device, DeviceFlags, DeviceLayer, DeviceList, DeviceType, LocationStatus, MMError};
+
+/// Device of the mock devices list
+#[derive(Debug, Clone)]
+pub struct MockDevice {
+    /// Address of the device
+    pub address: u8,
+    /// Type of the device
+    pub dtype: DeviceType,
+    /// Firmware version `(major, minor, second)`
+    pub firmware: (u8, u8, u8),
+    /// If the device is sleeping
+    pub is_sleeping: bool,
+    /// If the device is connected
+    pub is_connected: bool,
+}
+
+impl MockDevice {
+    /// Create an awake, connected device with firmware V7.00.
+    pub fn new(address: u8, dtype: DeviceType) -> Self {
+        Self {
+            address,
+            dtype,
+            firmware: (7, 0, 0),
+            is_sleeping: false,
+            is_connected: true,
+        }
+    }
+}
+
+/// Location of the mock last locations
+#[derive(Debug, Clone)]
+pub struct MockLocation {
+    /// Address of the device
+    pub address: u8,
+    /// Head index of the device
+    pub head_index: u8,
+    /// X coordinate, mm
+    pub x: i32,
+    /// Y coordinate, mm
+    pub y: i32,
+    /// Z coordinate, mm
+    pub z: i32,
+    /// Quality of positioning, 0...100%
+    pub q: u8,
+    /// Status flags
+    pub status_flag: u8,
+}
+
+impl MockLocation {
+    /// Create a location of head 0 with no status flags.
+    pub fn new(address: u8, x: i32, y: i32, z: i32, q: u8) -> Self {
+        Self {
+            address,
+            head_index: 0,
+            x,
+            y,
+            z,
+            q,
+            status_flag: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MockState {
+    devices: Vec<MockDevice>,
+    locations: Vec<MockLocation>,
+    is_new: bool,
+    location_payload: Vec<u8>,
+    payloads: BTreeMap<u8, Vec<u8>>,
+    update_rate_mhz: u32,
+    air_temperature: i8,
+    beacon_heights: BTreeMap<u8, i32>,
+    reset_downtime: u32,
+    rebooting: BTreeMap<u8, u32>,
+    submap_settings: BTreeMap<u8, Vec<u8>>,
+    is_port_available: bool,
+    open_attempts: u32,
+}
+
+impl MockState {
+    const fn new() -> Self {
+        Self {
+            devices: Vec::new(),
+            locations: Vec::new(),
+            is_new: false,
+            location_payload: Vec::new(),
+            payloads: BTreeMap::new(),
+            update_rate_mhz: 16000,
+            air_temperature: 20,
+            beacon_heights: BTreeMap::new(),
+            reset_downtime: 0,
+            rebooting: BTreeMap::new(),
+            submap_settings: BTreeMap::new(),
+            is_port_available: true,
+            open_attempts: 0,
+        }
+    }
+}
+
+static STATE: Mutex<MockState> = Mutex::new(MockState::new());
+
+fn state() -> MutexGuard<'static, MockState> {
+    STATE.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Serialize the tests using the global mock state, and reset it.
+#[cfg(test)]
+pub(crate) fn lock() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    let guard = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    *state() = MockState::new();
+    guard
+}
+
+/// Set if the port can be opened, `true` by default. Opening an unavailable port fails with `MMError::SerialPortError`.
+pub fn set_port_available(is_available: bool) {
+    state().is_port_available = is_available;
+}
+
+/// Get the number of attempts to open the port so far.
+pub fn open_attempts() -> u32 {
+    state().open_attempts
+}
+
+/// Set the devices returned by `get_device_list`, up to 255.
+pub fn set_devices(devices: &[MockDevice]) {
+    state().devices = devices.iter().take(u8::MAX as usize).cloned().collect();
+}
+
+/// Set the locations of the next locations read, up to 6, which are reported as new once.
+pub fn set_locations(locations: &[MockLocation]) {
+    let mut state = state();
+    state.locations = locations.iter().take(6).cloned().collect();
+    state.is_new = true;
+}
+
+/// Set for how many devices list reads a device is listed as disconnected after restoring its default settings,
+/// 0 by default.
+pub fn set_reset_downtime(reads: u32) {
+    state().reset_downtime = reads;
+}
+
+/// Set the user payload of the next locations reads, up to 255 bytes, see `DeviceList::last_payload`.
+pub fn set_location_payload(data: &[u8]) {
+    state().location_payload = data.iter().take(u8::MAX as usize).copied().collect();
+}
+
+/// Set the user payload returned by `get_from_device` for the device with the given address.
+pub fn set_payload(address: u8, data: &[u8]) {
+    state().payloads.insert(address, data.to_vec());
+}
+
+/// [`DeviceLayer`] serving a fixed devices list and a sequence of last locations
+///
+/// # Example
+///
+/// ```rust
+/// use marvelmind::{mock::{MockDevice, MockDeviceLayer, MockLocation}, DeviceLayer, DeviceListBuilder, DeviceType};
+///
+/// let layer = MockDeviceLayer::new(&[MockDevice::new(10, DeviceType::SuperBeaconHedgedog)]);
+/// layer.push_locations(&[MockLocation::new(10, 1000, 2000, 0, 100)]);
+/// layer.push_locations(&[MockLocation::new(10, 1500, 2000, 0, 40)]);
+///
+/// let mut devices_list = DeviceListBuilder::new()
+///     .with_quality_threshold(50)
+///     .build_with(&layer)
+///     .unwrap();
+///
+/// assert_eq!(devices_list.update_last_locations_with(&layer).unwrap(), vec![10]);
+/// assert!(devices_list.update_last_locations_with(&layer).unwrap().is_empty());
+/// assert_eq!(devices_list.get(10).unwrap().x(), 1000);
+/// ```
+#[derive(Debug, Default)]
+pub struct MockDeviceLayer {
+    devices: Vec<MockDevice>,
+    locations: Mutex<VecDeque<Vec<MockLocation>>>,
+}
+
+impl MockDeviceLayer {
+    /// Create a layer with the given devices list, up to 255 devices, and no locations.
+    pub fn new(devices: &[MockDevice]) -> Self {
+        Self {
+            devices: devices.iter().take(u8::MAX as usize).cloned().collect(),
+            locations: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Queue the locations of a read, which are reported as new once, in the order they were queued.
+    ///
+    /// Once the queue is empty, the reads return no new locations.
+    pub fn push_locations(&self, locations: &[MockLocation]) {
+        self.locations
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push_back(locations.to_vec());
+    }
+}
+
+impl DeviceLayer for MockDeviceLayer {
+    fn get_device_list(&self) -> Result<DeviceList, MMError> {
+        let devices = self.devices.iter().map(|device| Device {
+            address: device.address,
+            head_index: 0,
+            is_duplicated: false,
+            is_sleeping: device.is_sleeping,
+            v_major: device.firmware.0,
+            v_minor: device.firmware.1,
+            v_second: device.firmware.2,
+            dtype: device.dtype,
+            is_connected: device.is_connected,
+            x: 0,
+            y: 0,
+            z: 0,
+            q: 0,
+            location_status: LocationStatus::default(),
+            update_time: SystemTime::UNIX_EPOCH,
+            flags: DeviceFlags::from(u8::from(device.is_connected)),
+        });
+
+        DeviceList::from_devices(devices, true)
+    }
+
+    fn get_all_locations(&self) -> Result<Vec<Device>, MMError> {
+        let update_time = SystemTime::now();
+        let Some(locations) = self
+            .locations
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .pop_front()
+        else {
+            return Ok(Vec::new());
+        };
+
+        let devices = locations
+            .iter()
+            .filter(|location| location.address != 0 && location.q <= 100)
+            .map(|location| Device {
+                address: location.address,
+                head_index: location.head_index,
+                is_duplicated: false,
+                is_sleeping: false,
+                v_major: 0,
+                v_minor: 0,
+                v_second: 0,
+                dtype: DeviceType::Unknown(0),
+                is_connected: true,
+                x: location.x,
+                y: location.y,
+                z: location.z,
+                q: location.q,
+                location_status: LocationStatus(location.status_flag),
+                update_time,
+                flags: DeviceFlags::default(),
+            })
+            .collect();
+
+        Ok(devices)
+    }
+}
+
+pub(crate) mod ffi {
+    use std::mem;
+
+    use zerocopy::{
+        byteorder::little_endian::{I32, U32},
+        FromBytes,
+    };
+
+    use super::state;
+    use crate::{
+        MMBeaconLocation, MMDeviceList, MMDeviceVersion, MMLastDistances, MMLastLocations,
+        MMPayload, MMSubmapSettings,
+    };
+
+    macro_rules! unsupported {
+        ($($name:ident($($ty:ty),*);)*) => {
+            $(
+                pub(crate) unsafe fn $name($(_: $ty),*) -> bool {
+                    false
+                }
+            )*
+        };
+    }
+
+    pub(crate) unsafe fn mm_get_last_error(pdata: *mut U32) -> bool {
+        *pdata = match state().is_port_available {
+            true => U32::ZERO,
+            false => U32::new(2),
+        };
+        true
+    }
+
+    pub(crate) unsafe fn mm_api_version(pdata: *mut U32) -> bool {
+        *pdata = U32::new(0);
+        true
+    }
+
+    pub(crate) unsafe fn mm_open_port() -> bool {
+        let mut state = state();
+        state.open_attempts += 1;
+        state.is_port_available
+    }
+
+    pub(crate) unsafe fn mm_close_port() -> bool {
+        true
+    }
+
+    pub(crate) unsafe fn mm_get_devices_list(
+        pdata: *mut [u8; mem::size_of::<MMDeviceList>()],
+    ) -> bool {
+        let mut state = state();
+        let state = &mut *state;
+        let device_list = MMDeviceList::mut_from_bytes(&mut *pdata).unwrap();
+
+        device_list.num = state.devices.len() as u8;
+        for (mmdevice, device) in device_list.devices.iter_mut().zip(&state.devices) {
+            let is_rebooting = state.rebooting.contains_key(&device.address);
+
+            mmdevice.address = device.address;
+            mmdevice.is_sleeping = device.is_sleeping.into();
+            (mmdevice.v_major, mmdevice.v_minor, mmdevice.v_second) = device.firmware;
+            mmdevice.type_id = device.dtype.type_id();
+            mmdevice.flags = (device.is_connected && !is_rebooting).into();
+        }
+
+        state.rebooting.retain(|_, reads| {
+            *reads -= 1;
+            *reads > 0
+        });
+
+        true
+    }
+
+    pub(crate) unsafe fn mm_get_last_locations2(
+        pdata: *mut [u8; mem::size_of::<MMLastLocations>()],
+    ) -> bool {
+        let mut state = state();
+        (*pdata).fill(0);
+        let last_locations = MMLastLocations::mut_from_bytes(&mut *pdata).unwrap();
+
+        last_locations.is_new = state.is_new.into();
+        for (coord, location) in last_locations.coordinates.iter_mut().zip(&state.locations) {
+            coord.address = location.address;
+            coord.head_index = location.head_index;
+            coord.x = location.x.into();
+            coord.y = location.y.into();
+            coord.z = location.z.into();
+            coord.q = location.q;
+            coord.status_flag = location.status_flag;
+        }
+        last_locations.size_payload = state.location_payload.len() as u8;
+        last_locations.payload[..state.location_payload.len()]
+            .copy_from_slice(&state.location_payload);
+        state.is_new = false;
+
+        true
+    }
+
+    pub(crate) unsafe fn mm_send_to_device(
+        address: u8,
+        pdata: *const [u8; mem::size_of::<MMPayload>()],
+    ) -> bool {
+        let payload = MMPayload::ref_from_bytes(&*pdata).unwrap();
+        let data = payload.payload[..payload.size_payload as usize].to_vec();
+        state().payloads.insert(address, data);
+
+        true
+    }
+
+    pub(crate) unsafe fn mm_get_from_device(
+        address: u8,
+        pdata: *mut [u8; mem::size_of::<MMPayload>()],
+    ) -> bool {
+        let state = state();
+        let payload = MMPayload::mut_from_bytes(&mut *pdata).unwrap();
+
+        if let Some(data) = state.payloads.get(&address) {
+            payload.size_payload = data.len() as u8;
+            payload.payload[..data.len()].copy_from_slice(data);
+        }
+
+        true
+    }
+
+    pub(crate) unsafe fn mm_send_to_sleep_device(address: u8) -> bool {
+        let mut state = state();
+        let Some(device) = state
+            .devices
+            .iter_mut()
+            .find(|device| device.address == address)
+        else {
+            return false;
+        };
+
+        device.is_sleeping = true;
+        true
+    }
+
+    pub(crate) unsafe fn mm_wake_device(address: u8) -> bool {
+        let mut state = state();
+        let Some(device) = state
+            .devices
+            .iter_mut()
+            .find(|device| device.address == address)
+        else {
+            return false;
+        };
+
+        device.is_sleeping = false;
+        true
+    }
+
+    pub(crate) unsafe fn mm_set_default_settings(address: u8) -> bool {
+        let mut state = state();
+        if state.reset_downtime > 0 {
+            let downtime = state.reset_downtime;
+            state.rebooting.insert(address, downtime);
+        }
+        true
+    }
+
+    pub(crate) unsafe fn mm_get_submap_settings(
+        submap_id: u8,
+        pdata: *mut [u8; mem::size_of::<MMSubmapSettings>()],
+    ) -> bool {
+        match state().submap_settings.get(&submap_id) {
+            Some(bytes) => {
+                (*pdata).copy_from_slice(bytes);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) unsafe fn mm_set_submap_settings(
+        submap_id: u8,
+        pdata: *const [u8; mem::size_of::<MMSubmapSettings>()],
+    ) -> bool {
+        state().submap_settings.insert(submap_id, (*pdata).to_vec());
+        true
+    }
+
+    pub(crate) unsafe fn mm_get_beacon_height(address: u8, pdata: *mut I32) -> bool {
+        *pdata = state()
+            .beacon_heights
+            .get(&address)
+            .copied()
+            .unwrap_or_default()
+            .into();
+        true
+    }
+
+    pub(crate) unsafe fn mm_set_beacon_height(address: u8, pdata: *mut I32) -> bool {
+        state().beacon_heights.insert(address, (*pdata).into());
+        true
+    }
+
+    pub(crate) unsafe fn mm_get_update_rate_setting(pdata: *mut U32) -> bool {
+        *pdata = state().update_rate_mhz.into();
+        true
+    }
+
+    pub(crate) unsafe fn mm_set_update_rate_setting(pdata: *mut U32) -> bool {
+        state().update_rate_mhz = (*pdata).into();
+        true
+    }
+
+    pub(crate) unsafe fn mm_get_air_temperature(pdata: *mut i8) -> bool {
+        *pdata = state().air_temperature;
+        true
+    }
+
+    pub(crate) unsafe fn mm_set_air_temperature(pdata: *mut i8) -> bool {
+        state().air_temperature = *pdata;
+        true
+    }
+
+    unsupported! {
+        mm_get_device_version_and_id(u8, *mut [u8; mem::size_of::<MMDeviceVersion>()]);
+        mm_reset_device(u8);
+        mm_set_beacon_location(u8, *const [u8; mem::size_of::<MMBeaconLocation>()]);
+        mm_erase_map();
+        mm_add_submap(u8);
+        mm_delete_submap(u8);
+        mm_freeze_submap(u8);
+        mm_unfreeze_submap(u8);
+        mm_get_last_distances(*mut [u8; mem::size_of::<MMLastDistances>()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        get_device_list, get_submap_settings, open_port_cancellable, set_default_settings_verified,
+        set_submap_settings, velocity_between, CancellationToken, Connection, Coordinate,
+        DeviceListBuilder, DeviceType, MMError, SubmapSettings,
+    };
+    use std::{
+        collections::HashMap,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn open_port_without_timeout_tries_once() {
+        let _lock = lock();
+        set_port_available(false);
+
+        let res = open_port_cancellable(0, &CancellationToken::new());
+        assert!(matches!(res, Err(MMError::SerialPortError)));
+        assert_eq!(open_attempts(), 1);
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let res = open_port_cancellable(0, &cancel);
+        assert!(matches!(res, Err(MMError::Cancelled)));
+        assert_eq!(open_attempts(), 1, "no attempt once cancelled");
+    }
+
+    #[test]
+    fn device_list_is_parsed() {
+        let _lock = lock();
+        set_devices(&[
+            MockDevice::new(10, DeviceType::SuperBeaconHedgedog),
+            MockDevice {
+                firmware: (6, 240, 3),
+                is_sleeping: true,
+                is_connected: false,
+                ..MockDevice::new(2, DeviceType::BeaconHwV49)
+            },
+        ]);
+
+        let device_list = get_device_list().unwrap();
+        let devices = device_list.devices();
+
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].address(), 10);
+        assert_eq!(devices[0].dtype(), DeviceType::SuperBeaconHedgedog);
+        assert!(devices[0].is_connected());
+        assert!(!devices[0].is_sleeping());
+        assert_eq!(devices[1].address(), 2);
+        assert_eq!(devices[1].firmware_version_tuple(), (6, 240, 3));
+        assert!(!devices[1].is_connected());
+        assert!(devices[1].is_sleeping());
+    }
+
+    #[test]
+    fn updated_addresses_are_listed_once() {
+        let _lock = lock();
+        let connection = Connection::open(0).unwrap();
+        set_devices(&[
+            MockDevice::new(10, DeviceType::IndustrialSuperBeacon),
+            MockDevice::new(11, DeviceType::SuperBeaconHedgedog),
+        ]);
+        set_locations(&[
+            MockLocation::new(10, 1000, 0, 0, 100),
+            MockLocation::new(11, 2000, 0, 0, 100),
+            MockLocation {
+                head_index: 1,
+                ..MockLocation::new(10, 1100, 0, 0, 100)
+            },
+        ]);
+
+        let mut device_list = connection.get_device_list().unwrap();
+
+        assert_eq!(
+            device_list.update_last_locations(&connection).unwrap(),
+            vec![10, 11]
+        );
+        assert_eq!(device_list.devices().len(), 3, "a device per head");
+    }
+
+    #[test]
+    fn the_modem_is_not_sent_to_sleep() {
+        let _lock = lock();
+        let connection = Connection::open(0).unwrap();
+        set_devices(&[
+            MockDevice::new(1, DeviceType::ModemHwV51),
+            MockDevice::new(10, DeviceType::SuperBeaconHedgedog),
+        ]);
+
+        let device_list = connection.get_device_list().unwrap();
+        let Err(MMError::PartialFailure(failures)) =
+            device_list.sleep_devices(&connection, [1, 10])
+        else {
+            panic!("the modem is refused");
+        };
+        assert!(matches!(failures.get(&1), Some(MMError::InvalidAddress(1))));
+        assert!(!failures.contains_key(&10));
+
+        let device_list = connection.get_device_list().unwrap();
+        assert!(!device_list.get(1).unwrap().is_sleeping());
+        assert!(device_list.get(10).unwrap().is_sleeping());
+
+        assert_eq!(
+            device_list.sleep_devices(&connection, [10]).unwrap(),
+            vec![10]
+        );
+    }
+
+    #[test]
+    fn waking_goes_on_after_a_failure() {
+        let _lock = lock();
+        let connection = Connection::open(0).unwrap();
+        set_devices(&[
+            MockDevice {
+                is_sleeping: true,
+                ..MockDevice::new(10, DeviceType::SuperBeaconHedgedog)
+            },
+            MockDevice {
+                is_sleeping: true,
+                ..MockDevice::new(12, DeviceType::SuperBeacon)
+            },
+        ]);
+
+        let mut device_list = connection.get_device_list().unwrap();
+        // a sleeping device the modem no longer knows, which cannot be woken
+        device_list.devices.insert(
+            1,
+            Device {
+                address: 11,
+                ..device_list.devices[0].clone()
+            },
+        );
+
+        let Err(MMError::PartialFailure(failures)) = device_list.wake_all_sleeping(&connection)
+        else {
+            panic!("the unknown device cannot be woken");
+        };
+        assert_eq!(failures.keys().collect::<Vec<_>>(), [&11]);
+
+        let device_list = connection.get_device_list().unwrap();
+        assert_eq!(device_list.sleeping_devices().count(), 0);
+    }
+
+    #[test]
+    fn unknown_device_types_are_listed() {
+        let _lock = lock();
+        set_devices(&[
+            MockDevice::new(10, DeviceType::from(99)),
+            MockDevice::new(11, DeviceType::SuperBeacon),
+        ]);
+
+        let device_list = get_device_list().unwrap();
+
+        assert_eq!(device_list.devices().len(), 2);
+        assert_eq!(
+            device_list.get(10).unwrap().dtype(),
+            DeviceType::Unknown(99)
+        );
+        assert_eq!(device_list.get(10).unwrap().dtype().type_id(), 99);
+        assert_eq!(
+            device_list
+                .beacons()
+                .map(Device::address)
+                .collect::<Vec<_>>(),
+            vec![11]
+        );
+        assert_eq!(device_list.modem(), None);
+    }
+
+    #[test]
+    fn stale_locations_are_skipped() {
+        let _lock = lock();
+        let connection = Connection::open(0).unwrap();
+        set_devices(&[MockDevice::new(10, DeviceType::SuperBeaconHedgedog)]);
+        let mut device_list = connection.get_device_list().unwrap();
+
+        set_locations(&[MockLocation::new(10, 1000, 2000, 300, 90)]);
+        assert_eq!(
+            device_list.update_last_locations(&connection).unwrap(),
+            vec![10]
+        );
+        assert!(device_list.last_is_new());
+        let update_time = device_list.get(10).unwrap().update_time();
+
+        // not new: nothing is applied and the last location is kept
+        assert!(device_list
+            .update_last_locations(&connection)
+            .unwrap()
+            .is_empty());
+        assert!(!device_list.last_is_new());
+
+        let device = device_list.get(10).unwrap();
+        assert_eq!((device.x(), device.y(), device.z()), (1000, 2000, 300));
+        assert_eq!(device.q(), 90);
+        assert_eq!(device.update_time(), update_time);
+    }
+
+    #[test]
+    fn locations_below_min_q_are_skipped() {
+        let _lock = lock();
+        let connection = Connection::open(0).unwrap();
+        set_devices(&[
+            MockDevice::new(10, DeviceType::SuperBeaconHedgedog),
+            MockDevice::new(11, DeviceType::SuperBeaconHedgedog),
+            MockDevice::new(12, DeviceType::SuperBeaconHedgedog),
+        ]);
+        let mut device_list = connection.get_device_list().unwrap();
+
+        set_locations(&[
+            MockLocation::new(10, 1000, 0, 0, 50),
+            MockLocation::new(11, 2000, 0, 0, 49),
+            // above 100% is invalid
+            MockLocation::new(12, 3000, 0, 0, 101),
+        ]);
+        assert_eq!(
+            device_list
+                .update_last_locations_min_q(&connection, 50)
+                .unwrap(),
+            vec![10]
+        );
+        assert_eq!(device_list.get(10).unwrap().x(), 1000);
+        assert_eq!(device_list.get(11).unwrap().x(), 0);
+        assert_eq!(device_list.get(12).unwrap().x(), 0);
+    }
+
+    #[test]
+    fn other_head_is_added() {
+        let _lock = lock();
+        let connection = Connection::open(0).unwrap();
+        set_devices(&[MockDevice::new(10, DeviceType::IndustrialSuperBeacon)]);
+        let mut device_list = connection.get_device_list().unwrap();
+
+        set_locations(&[
+            MockLocation::new(10, 1000, 0, 0, 100),
+            MockLocation {
+                head_index: 1,
+                ..MockLocation::new(10, 1100, 0, 0, 100)
+            },
+        ]);
+        // the address is reported once for both heads
+        assert_eq!(
+            device_list.update_last_locations(&connection).unwrap(),
+            vec![10]
+        );
+
+        let devices = device_list.devices();
+        assert_eq!(devices.len(), 2);
+        assert_eq!((devices[0].head_index(), devices[0].x()), (0, 1000));
+        assert_eq!((devices[1].head_index(), devices[1].x()), (1, 1100));
+        assert_eq!(devices[1].dtype(), DeviceType::IndustrialSuperBeacon);
+
+        // the next location of head 1 updates the added device
+        set_locations(&[MockLocation {
+            head_index: 1,
+            ..MockLocation::new(10, 1200, 0, 0, 100)
+        }]);
+        assert_eq!(
+            device_list.update_last_locations(&connection).unwrap(),
+            vec![10]
+        );
+        assert_eq!(device_list.devices().len(), 2);
+        assert_eq!(device_list.devices()[1].x(), 1200);
+    }
+
+    #[test]
+    fn replayed_locations_keep_the_recorded_times() {
+        let _lock = lock();
+        let connection = Connection::open(0).unwrap();
+        set_devices(&[
+            MockDevice::new(10, DeviceType::SuperBeaconHedgedog),
+            MockDevice::new(11, DeviceType::SuperBeaconHedgedog),
+        ]);
+        let mut device_list = connection.get_device_list().unwrap();
+
+        // received at, measured at of 11, x of 10, recorded 1 s apart
+        let recording = [
+            (Duration::from_millis(1_000), Duration::from_millis(950), 0),
+            (
+                Duration::from_millis(2_000),
+                Duration::from_millis(1_950),
+                1000,
+            ),
+        ];
+
+        let mut previous = None;
+        for (received_at, measured_at, x) in recording {
+            let received_at = SystemTime::UNIX_EPOCH + received_at;
+            let measured_at = SystemTime::UNIX_EPOCH + measured_at;
+            set_locations(&[
+                MockLocation::new(10, x, 0, 0, 100),
+                MockLocation::new(11, 0, 0, 0, 100),
+            ]);
+
+            device_list
+                .update_last_locations_at(
+                    &connection,
+                    received_at,
+                    &HashMap::from([(11, measured_at)]),
+                )
+                .unwrap();
+
+            let current = device_list.get(10).unwrap().clone();
+            assert_eq!(current.update_time(), received_at);
+            assert_eq!(device_list.get(11).unwrap().update_time(), measured_at);
+
+            if let Some(previous) = previous {
+                let velocity = velocity_between(&previous, &current).unwrap();
+                assert_eq!(velocity.vx, 1000.0);
+            }
+            previous = Some(current);
+        }
+    }
+
+    #[test]
+    fn payload_is_truncated_to_its_size() {
+        let _lock = lock();
+        let connection = Connection::open(0).unwrap();
+        set_devices(&[MockDevice::new(10, DeviceType::SuperBeaconHedgedog)]);
+        let mut device_list = connection.get_device_list().unwrap();
+        assert_eq!(device_list.last_raw_payload(), None);
+
+        set_location_payload(&[1, 2, 3]);
+        set_locations(&[MockLocation::new(10, 0, 0, 0, 100)]);
+        device_list.update_last_locations(&connection).unwrap();
+        assert_eq!(device_list.last_payload(), Some(&[1_u8, 2, 3][..]));
+        assert_eq!(device_list.last_raw_payload(), Some(&[1_u8, 2, 3][..]));
+
+        set_location_payload(&[]);
+        device_list.update_last_locations(&connection).unwrap();
+        assert_eq!(device_list.last_payload(), None);
+        assert_eq!(device_list.last_raw_payload(), Some(&[][..]));
+    }
+
+    #[test]
+    fn builder_reads_the_layer() {
+        let layer = MockDeviceLayer::new(&[
+            MockDevice::new(2, DeviceType::SuperBeacon),
+            MockDevice::new(10, DeviceType::SuperBeaconHedgedog),
+        ]);
+
+        let device_list = DeviceListBuilder::new()
+            .with_initial_coordinate(2, Coordinate::from_meters(1.0, 2.0, 3.0))
+            .with_initial_coordinate(99, Coordinate::from_meters(1.0, 1.0, 1.0))
+            .with_quality_threshold(50)
+            .build_with(&layer)
+            .unwrap();
+
+        assert_eq!(device_list.devices().len(), 2);
+        assert_eq!(device_list.min_q(), 50);
+
+        let beacon = device_list.get(2).unwrap();
+        assert_eq!((beacon.x(), beacon.y(), beacon.z()), (1000, 2000, 3000));
+        let hedgehog = device_list.get(10).unwrap();
+        assert_eq!((hedgehog.x(), hedgehog.y(), hedgehog.z()), (0, 0, 0));
+        assert!(device_list.history().is_none());
+    }
+
+    #[test]
+    fn history_averages_the_updates() {
+        let layer = MockDeviceLayer::new(&[MockDevice::new(10, DeviceType::SuperBeaconHedgedog)]);
+        layer.push_locations(&[MockLocation::new(10, 1000, 0, 0, 100)]);
+        layer.push_locations(&[MockLocation::new(10, 2000, 0, 0, 100)]);
+        layer.push_locations(&[MockLocation::new(10, 4000, 0, 0, 100)]);
+
+        let mut device_list = DeviceListBuilder::new()
+            .with_history_depth(2)
+            .build_with(&layer)
+            .unwrap();
+        assert_eq!(device_list.history().unwrap().smoothed(10), None);
+
+        device_list.update_last_locations_with(&layer).unwrap();
+        device_list.update_last_locations_with(&layer).unwrap();
+        let smoothed = device_list.history().unwrap().smoothed(10).unwrap();
+        assert_eq!(smoothed.x, 1500);
+
+        device_list.update_last_locations_with(&layer).unwrap();
+        let smoothed = device_list.history().unwrap().smoothed(10).unwrap();
+        assert_eq!(smoothed.x, 3000);
+    }
+
+    #[test]
+    fn layer_locations_are_applied_in_order() {
+        let layer = MockDeviceLayer::new(&[
+            MockDevice::new(2, DeviceType::SuperBeacon),
+            MockDevice::new(10, DeviceType::SuperBeaconHedgedog),
+            MockDevice::new(11, DeviceType::SuperBeaconHedgedog),
+        ]);
+        let mut device_list = DeviceListBuilder::new()
+            .with_quality_threshold(50)
+            .build_with(&layer)
+            .unwrap();
+
+        layer.push_locations(&[
+            MockLocation::new(10, 1000, 0, 0, 100),
+            MockLocation::new(11, 2000, 0, 0, 100),
+        ]);
+        layer.push_locations(&[
+            MockLocation::new(11, 2100, 0, 0, 100),
+            // below the quality threshold of the builder
+            MockLocation::new(10, 1100, 0, 0, 40),
+        ]);
+        layer.push_locations(&[MockLocation::new(99, 0, 0, 0, 100)]);
+
+        assert_eq!(
+            device_list.update_last_locations_with(&layer).unwrap(),
+            vec![10, 11]
+        );
+        assert_eq!(
+            device_list.update_last_locations_with(&layer).unwrap(),
+            vec![11]
+        );
+        assert_eq!(device_list.get(10).unwrap().x(), 1000);
+        assert_eq!(device_list.get(11).unwrap().x(), 2100);
+
+        // unknown addresses are not added
+        assert!(device_list
+            .update_last_locations_with(&layer)
+            .unwrap()
+            .is_empty());
+        assert_eq!(device_list.devices().len(), 3);
+
+        // the queue is empty
+        assert!(device_list
+            .update_last_locations_with(&layer)
+            .unwrap()
+            .is_empty());
+        assert!(!device_list.last_is_new());
+    }
+
+    #[test]
+    fn default_settings_wait_for_the_device_to_return() {
+        let _lock = lock();
+        set_devices(&[MockDevice::new(2, DeviceType::SuperBeacon)]);
+        set_reset_downtime(3);
+
+        // returned once the device came back, not before it dropped nor after the reboot delay
+        assert!(set_default_settings_verified(2, Duration::MAX, Duration::from_secs(5)).unwrap());
+        assert!(state().rebooting.is_empty());
+    }
+
+    #[test]
+    fn default_settings_wait_for_the_reboot_delay() {
+        let _lock = lock();
+        set_devices(&[MockDevice::new(2, DeviceType::SuperBeacon)]);
+
+        // the device never drops out of the list
+        let t_start = Instant::now();
+        assert!(set_default_settings_verified(
+            2,
+            Duration::from_millis(200),
+            Duration::from_secs(5)
+        )
+        .unwrap());
+        assert!(t_start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn default_settings_time_out() {
+        let _lock = lock();
+        set_devices(&[MockDevice::new(2, DeviceType::SuperBeacon)]);
+        set_reset_downtime(u32::MAX);
+
+        assert!(
+            !set_default_settings_verified(2, Duration::MAX, Duration::from_millis(300)).unwrap()
+        );
+    }
+
+    /// Submap settings packet, with stale service zone point slots and reserved bytes.
+    const SUBMAP_SETTINGS: [u8; 94] = [
+        2, // starting beacon
+        2, 3, 4, 0, // starting set
+        1, 0, 1, 30, // 3D, only for Z, manual limitation distance, max distance
+        0x6a, 0xff, 0xc8, 0x00, 0x00, 0x00, // shift x -150, y 200, z 0
+        0x28, 0x23, // rotation 9000
+        0x10, 0x27, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // quaternion (10000, 0, 0, 0)
+        0x64, 0x00, 0x32, 0x00, // service zone thickness 100, hedgehogs height 50
+        0, 1, 1, 0, // frozen, locked, beacons higher, mirrored
+        2, 3, 4, 5, // beacons
+        1, 255, 255, 255, 255, 255, 255, 255, // nearby submaps
+        3,   // service zone points
+        0x00, 0x00, 0x00, 0x00, 0xf4, 0x01, 0x00, 0x00, 0xf4, 0x01, 0x90,
+        0x01, // (0, 0) (500, 0) (500, 400)
+        0x7b, 0x00, 0xd3, 0xff, // unused slot (123, -45)
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // unused slots
+        1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, // reserved
+    ];
+
+    #[test]
+    fn submap_settings_round_trip() {
+        let _lock = lock();
+        state().submap_settings.insert(1, SUBMAP_SETTINGS.to_vec());
+
+        let settings = get_submap_settings(1).unwrap();
+        assert_eq!(settings.starting_beacon, 2);
+        assert_eq!(settings.starting_set, [2, 3, 4, 0]);
+        assert!(settings.is_3d);
+        assert!(!settings.only_for_z);
+        assert_eq!(settings.max_distance_manual_m, 30);
+        assert_eq!(
+            (
+                settings.shift_x_cm,
+                settings.shift_y_cm,
+                settings.shift_z_cm
+            ),
+            (-150, 200, 0)
+        );
+        assert_eq!(settings.rotation_cdeg, 9000);
+        assert_eq!(settings.plane_quaternion, [10000, 0, 0, 0]);
+        assert!(settings.is_locked && settings.beacons_higher);
+        assert_eq!(settings.beacons, [2, 3, 4, 5]);
+        assert_eq!(
+            settings.service_zone_points,
+            vec![(0, 0), (500, 0), (500, 400)]
+        );
+        assert_eq!(settings.reserved(), &SUBMAP_SETTINGS[78..]);
+
+        // unchanged settings are written back byte for byte
+        set_submap_settings(1, &settings).unwrap();
+        assert_eq!(state().submap_settings[&1], SUBMAP_SETTINGS);
+
+        // a changed field only changes its own bytes
+        let mut settings = settings;
+        settings.shift_z_cm = 256;
+        set_submap_settings(1, &settings).unwrap();
+        let mut expected = SUBMAP_SETTINGS;
+        expected[13..15].copy_from_slice(&[0x00, 0x01]);
+        assert_eq!(state().submap_settings[&1], expected);
+    }
+
+    #[test]
+    fn submap_settings_are_validated() {
+        let _lock = lock();
+        state().submap_settings.insert(1, SUBMAP_SETTINGS.to_vec());
+        let settings = get_submap_settings(1).unwrap();
+
+        let invalid = [
+            SubmapSettings {
+                starting_beacon: 9,
+                ..settings.clone()
+            },
+            SubmapSettings {
+                starting_set: [2, 3, 9, 0],
+                ..settings.clone()
+            },
+            SubmapSettings {
+                rotation_cdeg: 36000,
+                ..settings.clone()
+            },
+            SubmapSettings {
+                service_zone_points: vec![(0, 0); 9],
+                ..settings.clone()
+            },
+        ];
+
+        for settings in &invalid {
+            assert!(matches!(
+                set_submap_settings(1, settings),
+                Err(MMError::InvalidArgument(_))
+            ));
+        }
+        assert_eq!(state().submap_settings[&1], SUBMAP_SETTINGS);
+
+        // no starting beacon
+        let settings = SubmapSettings {
+            starting_beacon: 0,
+            ..settings
+        };
+        assert!(set_submap_settings(1, &settings).is_ok());
+    }
+}
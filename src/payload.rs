@@ -0,0 +1,135 @@
+// Copyright 2025 wyzdwdz <wyzdwdz@gmail.com>
+//
+// Licensed under the MIT license <LICENSE or https://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to
+// those terms.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    thread::sleep,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{Connection, MMError};
+
+/// User payload received from Marvelmind<sup>&copy;</sup> device
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PayloadPacket {
+    from_address: u8,
+    data: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::unix_millis"))]
+    update_time: SystemTime,
+}
+
+impl PayloadPacket {
+    /// Get the address of the sending device.
+    #[inline]
+    pub fn from_address(&self) -> u8 {
+        self.from_address
+    }
+
+    /// Get the payload.
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Get the time when the payload was read.
+    #[inline]
+    pub fn update_time(&self) -> SystemTime {
+        self.update_time
+    }
+}
+
+/// Receiver of the user payloads sent uplink by devices, e.g. from the hedgehog's UART
+///
+/// Polls [`Connection::get_from_device`] for each address every `interval`.
+/// The modem keeps the last payload of a device, so a payload equal to the previous one
+/// of the same device is considered already received and skipped.
+///
+/// # Example
+///
+/// Echo the payloads back to their senders.
+///
+/// ```rust,no_run
+/// use marvelmind::{Connection, PayloadReceiver};
+/// use std::time::Duration;
+///
+/// let connection = Connection::open(30).unwrap();
+///
+/// let mut receiver = PayloadReceiver::new([10, 11], Duration::from_millis(10));
+///
+/// loop {
+///     if let Some(packet) = receiver.recv(&connection, Duration::from_secs(1)).unwrap() {
+///         connection.send_to_device(packet.from_address(), packet.data()).unwrap();
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PayloadReceiver {
+    addresses: Vec<u8>,
+    interval: Duration,
+    last_data: HashMap<u8, Vec<u8>>,
+    pending: VecDeque<PayloadPacket>,
+}
+
+impl PayloadReceiver {
+    /// Create a receiver of the payloads sent by the devices with `addresses`, polling every `interval`.
+    pub fn new<I>(addresses: I, interval: Duration) -> Self
+    where
+        I: IntoIterator<Item = u8>,
+    {
+        Self {
+            addresses: addresses.into_iter().collect(),
+            interval,
+            last_data: HashMap::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Wait for the next payload until `timeout` expires.
+    ///
+    /// Return `None` on timeout. Stop polling at the first failed read and return its error.
+    pub fn recv(
+        &mut self,
+        connection: &Connection,
+        timeout: Duration,
+    ) -> Result<Option<PayloadPacket>, MMError> {
+        let t_start = Instant::now();
+        loop {
+            if let Some(packet) = self.pending.pop_front() {
+                return Ok(Some(packet));
+            }
+
+            self.poll(connection)?;
+
+            if self.pending.is_empty() {
+                if t_start.elapsed() >= timeout {
+                    return Ok(None);
+                }
+
+                sleep(self.interval);
+            }
+        }
+    }
+
+    fn poll(&mut self, connection: &Connection) -> Result<(), MMError> {
+        for &address in &self.addresses {
+            let data = connection.get_from_device(address)?;
+
+            if data.is_empty() || self.last_data.get(&address) == Some(&data) {
+                continue;
+            }
+
+            self.last_data.insert(address, data.clone());
+            self.pending.push_back(PayloadPacket {
+                from_address: address,
+                data,
+                update_time: SystemTime::now(),
+            });
+        }
+
+        Ok(())
+    }
+}
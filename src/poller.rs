@@ -0,0 +1,402 @@
+// Copyright 2025 wyzdwdz <wyzdwdz@gmail.com>
+//
+// Licensed under the MIT license <LICENSE or https://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to
+// those terms.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use crate::{Connection, Device, DeviceList, MMError};
+
+/// Location update of a single Marvelmind<sup>&copy;</sup> device, see [`PollerHandle::spawn`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LocationUpdate {
+    address: u8,
+    head_index: u8,
+    x: i32,
+    y: i32,
+    z: i32,
+    q: u8,
+    #[cfg_attr(feature = "serde", serde(with = "crate::unix_millis"))]
+    time: SystemTime,
+}
+
+impl LocationUpdate {
+    pub(crate) fn from_device(device: &Device) -> Self {
+        Self {
+            address: device.address(),
+            head_index: device.head_index(),
+            x: device.x(),
+            y: device.y(),
+            z: device.z(),
+            q: device.q(),
+            time: device.update_time(),
+        }
+    }
+
+    /// Get the address of the device.
+    #[inline]
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Get the index of the ultrasonic head, for beacons with multiple heads sharing one address.
+    #[inline]
+    pub fn head_index(&self) -> u8 {
+        self.head_index
+    }
+
+    /// Get X coordinate, mm.
+    #[inline]
+    pub fn x(&self) -> i32 {
+        self.x
+    }
+
+    /// Get Y coordinate, mm.
+    #[inline]
+    pub fn y(&self) -> i32 {
+        self.y
+    }
+
+    /// Get Z coordinate, mm.
+    #[inline]
+    pub fn z(&self) -> i32 {
+        self.z
+    }
+
+    /// Get Quality of positioning, 0...100%.
+    #[inline]
+    pub fn q(&self) -> u8 {
+        self.q
+    }
+
+    /// Get the time when the location was read.
+    #[inline]
+    pub fn time(&self) -> SystemTime {
+        self.time
+    }
+}
+
+/// Polls the last locations of `device_list` every `interval_ms` until `is_stopped` is set,
+/// passing the updated `(address, head index)` pairs or the error of each poll to `on_poll`.
+///
+/// The loop also exits when `on_poll` returns `false`. The sleep between polls is cut short by unparking the thread.
+pub(crate) fn poll_loop(
+    device_list: &mut DeviceList,
+    interval_ms: &AtomicU64,
+    is_stopped: &AtomicBool,
+    mut on_poll: impl FnMut(&DeviceList, Result<Vec<(u8, u8)>, MMError>) -> bool,
+) {
+    while !is_stopped.load(Ordering::Relaxed) {
+        let res = device_list.update_last_heads();
+        if !on_poll(device_list, res) {
+            break;
+        }
+
+        thread::park_timeout(Duration::from_millis(interval_ms.load(Ordering::Relaxed)));
+    }
+}
+
+/// Sends a copy of the devices list over `tx` if any device was updated. Failed polls are skipped.
+///
+/// Return `false` if the receiver is dropped.
+pub(crate) fn send_device_list(
+    tx: &Sender<DeviceList>,
+    device_list: &DeviceList,
+    res: Result<Vec<(u8, u8)>, MMError>,
+) -> bool {
+    match res {
+        Ok(updated) if !updated.is_empty() => tx.send(device_list.clone()).is_ok(),
+        _ => true,
+    }
+}
+
+/// Background thread polling the last locations of a devices list
+///
+/// A copy of the devices list is sent over the channel whenever a location is updated.
+/// The poller takes the connection, which is given back by [`into_parts`](Self::into_parts).
+/// The thread is stopped when the poller is dropped. See [`PollerHandle::spawn`] to receive
+/// the updates of each device instead.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use marvelmind::{Connection, LocationPoller};
+/// use std::time::Duration;
+///
+/// let connection = Connection::open(30).unwrap();
+///
+/// let device_list = connection.get_device_list().unwrap();
+/// let (mut poller, rx) = LocationPoller::new(connection, device_list, Duration::from_millis(1));
+/// poller.start();
+///
+/// for device_list in rx {
+///     println!("{}", device_list);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct LocationPoller {
+    parts: Option<(Connection, DeviceList)>,
+    tx: Sender<DeviceList>,
+    interval: Duration,
+    is_stopped: Arc<AtomicBool>,
+    handle: Option<JoinHandle<(Connection, DeviceList)>>,
+}
+
+impl LocationPoller {
+    /// Create a poller of `device_list` polling every `interval`, and the receiver of its updates.
+    ///
+    /// The poller does nothing until `start` is called.
+    pub fn new(
+        connection: Connection,
+        device_list: DeviceList,
+        interval: Duration,
+    ) -> (Self, Receiver<DeviceList>) {
+        let (tx, rx) = mpsc::channel();
+
+        let poller = Self {
+            parts: Some((connection, device_list)),
+            tx,
+            interval,
+            is_stopped: Arc::new(AtomicBool::new(false)),
+            handle: None,
+        };
+
+        (poller, rx)
+    }
+
+    /// If the background thread is running.
+    #[inline]
+    pub fn is_running(&self) -> bool {
+        self.handle.is_some()
+    }
+
+    /// Spawn the background thread. Do nothing if it is already running.
+    ///
+    /// Failed location reads are skipped. The thread exits by itself if the receiver is dropped.
+    pub fn start(&mut self) {
+        let Some((connection, mut device_list)) = self.parts.take() else {
+            return;
+        };
+
+        let tx = self.tx.clone();
+        let interval_ms = AtomicU64::new(self.interval.as_millis() as u64);
+        let is_stopped = self.is_stopped.clone();
+        is_stopped.store(false, Ordering::Relaxed);
+
+        self.handle = Some(thread::spawn(move || {
+            poll_loop(
+                &mut device_list,
+                &interval_ms,
+                &is_stopped,
+                |device_list, res| send_device_list(&tx, device_list, res),
+            );
+
+            (connection, device_list)
+        }));
+    }
+
+    /// Signal the background thread to exit and join it. Do nothing if it is not running.
+    ///
+    /// The poller can be started again afterwards.
+    pub fn stop(&mut self) {
+        let Some(handle) = self.handle.take() else {
+            return;
+        };
+
+        self.is_stopped.store(true, Ordering::Relaxed);
+        handle.thread().unpark();
+
+        if let Ok(parts) = handle.join() {
+            self.parts = Some(parts);
+        }
+    }
+
+    /// Stop the background thread and get the connection and the devices list back.
+    ///
+    /// Return `None` if the thread panicked, which closes the port.
+    pub fn into_parts(mut self) -> Option<(Connection, DeviceList)> {
+        self.stop();
+        self.parts.take()
+    }
+}
+
+impl Drop for LocationPoller {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Handle of a background thread polling the last locations, which sends the update of each device
+///
+/// The handle takes the connection, which is given back by [`stop`](Self::stop).
+/// The thread is stopped when the handle is dropped, which closes the port.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use marvelmind::{Connection, PollerHandle};
+/// use std::time::Duration;
+///
+/// let connection = Connection::open(30).unwrap();
+///
+/// let (handle, rx) = PollerHandle::spawn(connection, Duration::from_millis(10));
+///
+/// for update in rx.iter().take(100) {
+///     match update {
+///         Ok(update) => println!("{}: {} {} {}", update.address(), update.x(), update.y(), update.z()),
+///         Err(err) => eprintln!("{}", err),
+///     }
+/// }
+///
+/// handle.set_interval(Duration::from_millis(100));
+/// let connection = handle.stop().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct PollerHandle {
+    interval_ms: Arc<AtomicU64>,
+    is_stopped: Arc<AtomicBool>,
+    handle: Option<JoinHandle<Connection>>,
+}
+
+impl PollerHandle {
+    /// Spawn a thread polling the last locations every `interval`,
+    /// which sends a [`LocationUpdate`] for each updated device, or head of a multi-head beacon, over the channel.
+    ///
+    /// The thread reads the devices list with `get_device_list` once at start.
+    /// Api errors are sent over the channel, the thread exits after a failed devices list read.
+    /// The thread exits by itself if the receiver is dropped.
+    pub fn spawn(
+        connection: Connection,
+        interval: Duration,
+    ) -> (Self, Receiver<Result<LocationUpdate, MMError>>) {
+        let (tx, rx) = mpsc::channel();
+
+        let interval_ms = Arc::new(AtomicU64::new(interval.as_millis() as u64));
+        let is_stopped = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let interval_ms = interval_ms.clone();
+            let is_stopped = is_stopped.clone();
+
+            thread::spawn(move || {
+                let mut device_list = match connection.get_device_list() {
+                    Ok(device_list) => device_list,
+                    Err(err) => {
+                        let _ = tx.send(Err(err));
+                        return connection;
+                    }
+                };
+
+                poll_loop(
+                    &mut device_list,
+                    &interval_ms,
+                    &is_stopped,
+                    |device_list, res| match res {
+                        Ok(updated) => updated.into_iter().all(|(address, head_index)| {
+                            match device_list.get_head(address, head_index) {
+                                Some(device) => {
+                                    tx.send(Ok(LocationUpdate::from_device(device))).is_ok()
+                                }
+                                None => true,
+                            }
+                        }),
+                        Err(err) => tx.send(Err(err)).is_ok(),
+                    },
+                );
+
+                connection
+            })
+        };
+
+        let handle = Self {
+            interval_ms,
+            is_stopped,
+            handle: Some(handle),
+        };
+
+        (handle, rx)
+    }
+
+    /// Get the polling interval.
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms.load(Ordering::Relaxed))
+    }
+
+    /// Change the polling interval, which takes effect from the next poll.
+    pub fn set_interval(&self, interval: Duration) {
+        self.interval_ms
+            .store(interval.as_millis() as u64, Ordering::Relaxed);
+
+        if let Some(handle) = &self.handle {
+            handle.thread().unpark();
+        }
+    }
+
+    /// If the background thread is running.
+    pub fn is_running(&self) -> bool {
+        self.handle
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+    }
+
+    /// Signal the background thread to exit, join it and get the connection back.
+    ///
+    /// Return `None` if the thread panicked, which closes the port.
+    pub fn stop(mut self) -> Option<Connection> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Option<Connection> {
+        let handle = self.handle.take()?;
+
+        self.is_stopped.store(true, Ordering::Relaxed);
+        handle.thread().unpark();
+        handle.join().ok()
+    }
+}
+
+impl Drop for PollerHandle {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::{
+        mock::{self, MockDevice, MockLocation},
+        DeviceType,
+    };
+
+    #[test]
+    fn updates_carry_their_head() {
+        let _lock = mock::lock();
+        mock::set_devices(&[MockDevice::new(10, DeviceType::IndustrialSuperBeacon)]);
+        mock::set_locations(&[
+            MockLocation::new(10, 1000, 0, 0, 100),
+            MockLocation {
+                head_index: 1,
+                ..MockLocation::new(10, 1100, 0, 0, 100)
+            },
+        ]);
+
+        let (handle, rx) =
+            PollerHandle::spawn(Connection::open(0).unwrap(), Duration::from_millis(1));
+        let updates: Vec<_> = rx.iter().take(2).map(Result::unwrap).collect();
+        handle.stop();
+
+        assert_eq!((updates[0].head_index(), updates[0].x()), (0, 1000));
+        assert_eq!((updates[1].head_index(), updates[1].x()), (1, 1100));
+    }
+}
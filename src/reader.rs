@@ -0,0 +1,116 @@
+// Copyright 2025 wyzdwdz <wyzdwdz@gmail.com>
+//
+// Licensed under the MIT license <LICENSE or https://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to
+// those terms.
+
+use std::{collections::HashMap, mem, time::SystemTime};
+
+use zerocopy::FromBytes;
+
+use crate::{
+    get_last_error, mm_get_last_locations2, traced, Connection, DeviceList, MMError,
+    MMLastLocations,
+};
+
+/// Reader of the last locations owning a reusable buffer, for hot polling loops
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use marvelmind::{Connection, LocationsReader};
+///
+/// let connection = Connection::open(30).unwrap();
+///
+/// let mut devices_list = connection.get_device_list().unwrap();
+/// let mut reader = LocationsReader::new();
+///
+/// loop {
+///     if reader.read_into(&connection, &mut devices_list).unwrap() {
+///         println!("{}", devices_list);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct LocationsReader {
+    pdata: [u8; mem::size_of::<MMLastLocations>()],
+}
+
+impl LocationsReader {
+    /// Create a reader with a zeroed buffer.
+    pub fn new() -> Self {
+        Self {
+            pdata: [0_u8; mem::size_of::<MMLastLocations>()],
+        }
+    }
+
+    /// Update the last locations of `device_list` like [`DeviceList::update_last_locations`],
+    /// reusing the buffer of the reader, which is not cleared between reads.
+    /// Like the update, only the first `size_payload` bytes of the payload are read.
+    ///
+    /// Return `true` if any device was updated.
+    pub fn read_into(
+        &mut self,
+        _connection: &Connection,
+        device_list: &mut DeviceList,
+    ) -> Result<bool, MMError> {
+        let res = traced("mm_get_last_locations2", || unsafe {
+            mm_get_last_locations2(&mut self.pdata)
+        });
+
+        if !res {
+            return Err(get_last_error());
+        }
+
+        let last_locations = MMLastLocations::ref_from_bytes(&self.pdata).unwrap();
+        let updated = device_list.apply_locations(
+            last_locations,
+            SystemTime::now(),
+            &HashMap::new(),
+            device_list.min_q,
+            &[],
+        );
+
+        Ok(!updated.is_empty())
+    }
+}
+
+impl Default for LocationsReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::{
+        mock::{self, MockDevice, MockLocation},
+        DeviceType,
+    };
+
+    #[test]
+    fn previous_locations_are_not_read_again() {
+        let _lock = mock::lock();
+        let connection = Connection::open(0).unwrap();
+        mock::set_devices(&[
+            MockDevice::new(10, DeviceType::SuperBeaconHedgedog),
+            MockDevice::new(11, DeviceType::SuperBeaconHedgedog),
+        ]);
+        mock::set_locations(&[
+            MockLocation::new(10, 1000, 0, 0, 100),
+            MockLocation::new(11, 2000, 0, 0, 100),
+        ]);
+
+        let mut device_list = connection.get_device_list().unwrap();
+        let mut reader = LocationsReader::new();
+        assert!(reader.read_into(&connection, &mut device_list).unwrap());
+        let update_time = device_list.get(11).unwrap().update_time();
+
+        mock::set_locations(&[MockLocation::new(10, 1500, 0, 0, 100)]);
+        assert!(reader.read_into(&connection, &mut device_list).unwrap());
+
+        assert_eq!(device_list.get(10).unwrap().x(), 1500);
+        assert_eq!(device_list.get(11).unwrap().update_time(), update_time);
+    }
+}
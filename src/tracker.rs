@@ -0,0 +1,287 @@
+// Copyright 2025 wyzdwdz <wyzdwdz@gmail.com>
+//
+// Licensed under the MIT license <LICENSE or https://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed except according to
+// those terms.
+
+use std::{
+    fmt,
+    panic::{self, AssertUnwindSafe},
+};
+
+use crate::{Connection, DeviceList, LocationUpdate, MMError};
+
+/// Identifier of a subscription of a [`Tracker`], see [`Tracker::unsubscribe`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SubscriptionId(u64);
+
+/// Panic of a subscription callback caught by [`Tracker::poll`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallbackPanic {
+    id: SubscriptionId,
+    address: u8,
+    message: String,
+}
+
+impl CallbackPanic {
+    /// Get the subscription of the callback.
+    #[inline]
+    pub fn id(&self) -> SubscriptionId {
+        self.id
+    }
+
+    /// Get the address of the location update passed to the callback.
+    #[inline]
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Get the panic message, empty if the panic payload is not a string.
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for CallbackPanic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "callback of subscription {} panicked on device {}: {}",
+            self.id.0, self.address, self.message
+        )
+    }
+}
+
+type Callback = Box<dyn FnMut(&LocationUpdate) + Send>;
+
+struct Subscription {
+    id: SubscriptionId,
+    address: Option<u8>,
+    callback: Callback,
+}
+
+/// Devices list calling the subscribed closures whenever a device receives a new location
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use marvelmind::{Connection, Tracker};
+/// use std::{thread::sleep, time::Duration};
+///
+/// let connection = Connection::open(30).unwrap();
+///
+/// let mut tracker = Tracker::new(connection.get_device_list().unwrap());
+///
+/// let id = tracker.subscribe(10, |update| println!("hedgehog: {} {}", update.x(), update.y()));
+/// tracker.subscribe_all(|update| println!("{}: q={}", update.address(), update.q()));
+///
+/// for _ in 0..1000 {
+///     for panic in tracker.poll(&connection).unwrap() {
+///         eprintln!("{}", panic);
+///     }
+///
+///     sleep(Duration::from_millis(10));
+/// }
+///
+/// tracker.unsubscribe(id);
+/// ```
+pub struct Tracker {
+    device_list: DeviceList,
+    subscriptions: Vec<Subscription>,
+    next_id: u64,
+}
+
+impl Tracker {
+    /// Create a tracker of `device_list` with no subscriptions.
+    pub fn new(device_list: DeviceList) -> Self {
+        Self {
+            device_list,
+            subscriptions: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Get the tracked devices list.
+    #[inline]
+    pub fn device_list(&self) -> &DeviceList {
+        &self.device_list
+    }
+
+    /// Get the tracked devices list back.
+    pub fn into_device_list(self) -> DeviceList {
+        self.device_list
+    }
+
+    /// Call `f` whenever the device with the given address receives a new location.
+    pub fn subscribe(
+        &mut self,
+        address: u8,
+        f: impl FnMut(&LocationUpdate) + Send + 'static,
+    ) -> SubscriptionId {
+        self.insert(Some(address), Box::new(f))
+    }
+
+    /// Call `f` whenever any device receives a new location.
+    pub fn subscribe_all(
+        &mut self,
+        f: impl FnMut(&LocationUpdate) + Send + 'static,
+    ) -> SubscriptionId {
+        self.insert(None, Box::new(f))
+    }
+
+    /// Remove a subscription. Return `false` if it was already removed.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let len = self.subscriptions.len();
+        self.subscriptions
+            .retain(|subscription| subscription.id != id);
+        self.subscriptions.len() != len
+    }
+
+    /// Update the last locations of the devices list and call the subscriptions of the updated devices,
+    /// in the order they were subscribed. Each updated head of a multi-head beacon is a separate call.
+    ///
+    /// A panicking callback does not stop the others, its panic is returned instead
+    /// and it stays subscribed. The panic is still reported by the panic hook.
+    pub fn poll(&mut self, _connection: &Connection) -> Result<Vec<CallbackPanic>, MMError> {
+        let updated = self.device_list.update_last_heads()?;
+        let mut panics = Vec::new();
+
+        for (address, head_index) in updated {
+            let Some(device) = self.device_list.get_head(address, head_index) else {
+                continue;
+            };
+            let update = LocationUpdate::from_device(device);
+
+            for subscription in &mut self.subscriptions {
+                if subscription
+                    .address
+                    .is_some_and(|sub_address| sub_address != address)
+                {
+                    continue;
+                }
+
+                let callback = &mut subscription.callback;
+                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| callback(&update))) {
+                    let message = if let Some(msg) = payload.downcast_ref::<&str>() {
+                        msg.to_string()
+                    } else if let Some(msg) = payload.downcast_ref::<String>() {
+                        msg.clone()
+                    } else {
+                        String::new()
+                    };
+
+                    warn!(
+                        "subscription {} panicked on device {}: {}",
+                        subscription.id.0, address, message
+                    );
+
+                    panics.push(CallbackPanic {
+                        id: subscription.id,
+                        address,
+                        message,
+                    });
+                }
+            }
+        }
+
+        Ok(panics)
+    }
+
+    fn insert(&mut self, address: Option<u8>, callback: Callback) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+
+        self.subscriptions.push(Subscription {
+            id,
+            address,
+            callback,
+        });
+
+        id
+    }
+}
+
+impl fmt::Debug for Tracker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Tracker")
+            .field("device_list", &self.device_list)
+            .field("subscriptions", &self.subscriptions.len())
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::{
+        mock::{self, MockDevice, MockLocation},
+        DeviceType,
+    };
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn subscriptions_get_the_updated_head() {
+        let _lock = mock::lock();
+        let connection = Connection::open(0).unwrap();
+        mock::set_devices(&[
+            MockDevice::new(10, DeviceType::IndustrialSuperBeacon),
+            MockDevice::new(11, DeviceType::SuperBeaconHedgedog),
+        ]);
+        let mut tracker = Tracker::new(connection.get_device_list().unwrap());
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let id = tracker.subscribe(10, {
+            let seen = seen.clone();
+            move |update| seen.lock().unwrap().push((update.head_index(), update.x()))
+        });
+
+        mock::set_locations(&[
+            MockLocation::new(11, 500, 0, 0, 100),
+            MockLocation {
+                head_index: 1,
+                ..MockLocation::new(10, 1100, 0, 0, 100)
+            },
+        ]);
+        assert!(tracker.poll(&connection).unwrap().is_empty());
+        assert_eq!(*seen.lock().unwrap(), vec![(1, 1100)]);
+
+        assert!(tracker.unsubscribe(id));
+        assert!(!tracker.unsubscribe(id));
+    }
+
+    #[test]
+    fn panics_are_returned() {
+        let _lock = mock::lock();
+        let connection = Connection::open(0).unwrap();
+        mock::set_devices(&[
+            MockDevice::new(10, DeviceType::SuperBeaconHedgedog),
+            MockDevice::new(11, DeviceType::SuperBeaconHedgedog),
+        ]);
+        let mut tracker = Tracker::new(connection.get_device_list().unwrap());
+
+        let calls = Arc::new(Mutex::new(0));
+        let id = tracker.subscribe_all(|update| {
+            if update.address() == 10 {
+                panic!("bad fix {}", update.x());
+            }
+        });
+        tracker.subscribe_all({
+            let calls = calls.clone();
+            move |_| *calls.lock().unwrap() += 1
+        });
+
+        mock::set_locations(&[
+            MockLocation::new(10, 1000, 0, 0, 100),
+            MockLocation::new(11, 2000, 0, 0, 100),
+        ]);
+        let panics = tracker.poll(&connection).unwrap();
+
+        assert_eq!(panics.len(), 1);
+        assert_eq!(panics[0].id(), id);
+        assert_eq!(panics[0].address(), 10);
+        assert_eq!(panics[0].message(), "bad fix 1000");
+        // the other subscription still got both updates
+        assert_eq!(*calls.lock().unwrap(), 2);
+    }
+}